@@ -1,7 +1,5 @@
 use vines::controller::Controller;
-use vines::cpu::mappers::basic_mapper::*;
-use vines::cpu::*;
-use vines::ppu::PPU;
+use vines::nes::Nes;
 use vines::rom::*;
 
 use sdl2::event::Event;
@@ -14,6 +12,8 @@ use std::env;
 use std::fs::read;
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
     let sdl_context = sdl2::init().unwrap();
@@ -24,7 +24,7 @@ fn main() {
         .build()
         .unwrap();
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    let mut canvas = window.into_canvas().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
     canvas.set_scale(1.0, 1.0).unwrap();
 
@@ -33,43 +33,28 @@ fn main() {
         .create_texture_target(PixelFormatEnum::RGB24, 256, 240)
         .unwrap();
 
-    let mut rng = rand::thread_rng();
-
     let rom_bytes = read(PathBuf::from("examples/color_test/color_test.nes")).unwrap();
     let rom = Rom::try_from(&rom_bytes).unwrap();
     println!("prg rom len: {}", rom.prg_rom.len());
     println!("chr rom len: {}", rom.chr_rom.len());
+    let mut nes = Nes::new(rom);
+    let controller = nes.controller();
+    // PAL and Dendy games run at 50 frames per second
+    let frame_duration = Duration::from_secs_f64(1.0 / nes.region().frames_per_second());
+    let mut next_frame = Instant::now();
 
-    let ppu = Rc::new(RefCell::new(PPU::new(rom.chr_rom.clone())));
-    let controller = Rc::new(RefCell::new(Controller::new()));
-    let cpu_mapper = Rc::new(RefCell::new(BasicMapper::new(
-        rom,
-        ppu.clone(),
-        controller.clone(),
-    )));
-    let mut cpu = CPU::new(cpu_mapper.clone());
-
-    cpu.reset();
-
-    let mut total_cycles: usize = 0;
     loop {
         handle_user_input(controller.clone(), &mut event_pump);
 
-        if total_cycles % 341 == 0 {
-            texture
-                .update(None, &ppu.borrow().frame.bytes, 256 * 3)
-                .unwrap();
-            canvas.copy(&texture, None, None).unwrap();
-            canvas.present();
-        }
-
-        let instruction_result = cpu.execute_next_instruction();
+        let frame = nes.run_frame();
+        texture.update(None, &frame.bytes, 256 * 3).unwrap();
+        canvas.copy(&texture, None, None).unwrap();
+        canvas.present();
 
-        for _ in 0..instruction_result.executed_cycles {
-            ppu.borrow_mut().tick();
+        next_frame += frame_duration;
+        if let Some(wait) = next_frame.checked_duration_since(Instant::now()) {
+            sleep(wait);
         }
-
-        total_cycles += instruction_result.executed_cycles as usize;
     }
 }
 fn handle_user_input(controller: Rc<RefCell<Controller>>, event_pump: &mut EventPump) {
@@ -1,8 +1,8 @@
-use vines::cpu::mappers::basic_mapper::*;
-use vines::ppu::PPU;
+use vines::controller::*;
+use vines::nes::Nes;
 use vines::rom::*;
-use vines::{controller::*, cpu::*};
 
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
@@ -13,6 +13,8 @@ use std::env;
 use std::fs::read;
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 fn main() {
     env::set_var("RUST_BACKTRACE", "full");
     let sdl_context = sdl2::init().unwrap();
@@ -23,7 +25,7 @@ fn main() {
         .build()
         .unwrap();
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    let mut canvas = window.into_canvas().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
     canvas.set_scale(1.0, 1.0).unwrap();
 
@@ -36,40 +38,37 @@ fn main() {
     let rom = Rom::try_from(&rom_bytes).unwrap();
     println!("prg rom len: {}", rom.prg_rom.len());
     println!("chr rom len: {}", rom.chr_rom.len());
-    let ppu = Rc::new(RefCell::new(PPU::new(rom.chr_rom.clone())));
+    let mut nes = Nes::new(rom);
+    let controller = nes.controller();
+    let apu = nes.apu();
 
-    let controller = Rc::new(RefCell::new(Controller::new()));
-    let cpu_mapper = Rc::new(RefCell::new(BasicMapper::new(
-        rom,
-        ppu.clone(),
-        controller.clone(),
-    )));
-    let mut cpu = CPU::new(cpu_mapper.clone());
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let audio_spec = AudioSpecDesired {
+        freq: Some(apu.borrow().sample_rate as i32),
+        channels: Some(1),
+        samples: None,
+    };
+    let audio: AudioQueue<f32> = audio_subsystem.open_queue(None, &audio_spec).unwrap();
+    audio.resume();
+    // PAL and Dendy games run at 50 frames per second
+    let frame_duration = Duration::from_secs_f64(1.0 / nes.region().frames_per_second());
+    let mut next_frame = Instant::now();
 
-    cpu.reset();
-
-    let mut total_cycles: usize = 0;
     loop {
         handle_user_input(controller.clone(), &mut event_pump);
 
-        if total_cycles % 6820 == 0 {
-            texture
-                .update(None, &ppu.borrow().frame.bytes, 256 * 3)
-                .unwrap();
-            canvas.copy(&texture, None, None).unwrap();
-            canvas.present();
-        }
-
-        let instruction_result = cpu.execute_next_instruction();
+        let frame = nes.run_frame();
+        texture.update(None, &frame.bytes, 256 * 3).unwrap();
+        canvas.copy(&texture, None, None).unwrap();
+        canvas.present();
+        audio.queue(&apu.borrow_mut().take_samples());
 
-        for _ in 0..instruction_result.executed_cycles {
-            ppu.borrow_mut().tick();
+        next_frame += frame_duration;
+        if let Some(wait) = next_frame.checked_duration_since(Instant::now()) {
+            sleep(wait);
         }
-
-        total_cycles += instruction_result.executed_cycles as usize;
     }
 }
-
 fn handle_user_input(controller: Rc<RefCell<Controller>>, event_pump: &mut EventPump) {
     for event in event_pump.poll_iter() {
         match event {
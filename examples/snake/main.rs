@@ -1,9 +1,7 @@
 use rand::Rng;
-use vines::controller::Controller;
 use vines::cpu::mappers::basic_mapper::*;
-use vines::cpu::*;
 use vines::memory::Memory;
-use vines::ppu::PPU;
+use vines::nes::Nes;
 use vines::rom::*;
 
 use sdl2::event::Event;
@@ -39,17 +37,9 @@ fn main() {
 
     let rom_bytes = read(PathBuf::from("examples/snake/snake.nes")).unwrap();
     let rom = Rom::try_from(&rom_bytes).unwrap();
-    let ppu = Rc::new(RefCell::new(PPU::new(vec![])));
-    let controller = Rc::new(RefCell::new(Controller::new()));
-    let cpu_mapper = Rc::new(RefCell::new(BasicMapper::new(
-        rom.clone(),
-        ppu.clone(),
-        controller,
-    )));
-    let mut cpu = CPU::new(cpu_mapper.clone());
-
     println!("rom len: {}", rom.prg_rom.len());
-    cpu.reset();
+    let mut nes = Nes::new(rom);
+    let cpu_mapper = nes.mapper();
 
     loop {
         handle_user_input(cpu_mapper.clone(), &mut event_pump);
@@ -63,7 +53,7 @@ fn main() {
 
         ::std::thread::sleep(std::time::Duration::new(0, 2000));
 
-        cpu.execute_next_instruction();
+        nes.step_instruction();
     }
 }
 fn handle_user_input(mapper: Rc<RefCell<BasicMapper>>, event_pump: &mut EventPump) {
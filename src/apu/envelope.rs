@@ -0,0 +1,112 @@
+// https://www.nesdev.org/wiki/APU_Envelope
+// Either a constant volume, or a sawtooth that decays from 15 to 0 once every period + 1 quarter frames.
+pub struct Envelope {
+    is_start_pending: bool,
+    is_looping: bool,
+    is_constant_volume: bool,
+    // the constant volume, and the period of the divider at the same time
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Envelope {
+            is_start_pending: false,
+            is_looping: false,
+            is_constant_volume: false,
+            volume: 0,
+            divider: 0,
+            decay: 0,
+        }
+    }
+
+    // --LC VVVV, the loop flag doubles as the halt flag of the length counter
+    pub fn write(&mut self, data: u8) {
+        self.is_looping = data & 0b0010_0000 != 0;
+        self.is_constant_volume = data & 0b0001_0000 != 0;
+        self.volume = data & 0b0000_1111;
+    }
+
+    // The decay starts over on the next quarter frame
+    pub fn restart(&mut self) {
+        self.is_start_pending = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.is_start_pending {
+            self.is_start_pending = false;
+            self.decay = 15;
+            self.divider = self.volume;
+            return;
+        }
+
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.volume;
+        if self.decay > 0 {
+            self.decay -= 1;
+        } else if self.is_looping {
+            self.decay = 15;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        return match self.is_constant_volume {
+            true => self.volume,
+            false => self.decay,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test_envelope {
+    use super::*;
+
+    #[test]
+    fn test_constant_volume() {
+        let mut envelope = Envelope::new();
+        envelope.write(0b0001_0111);
+        envelope.restart();
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(7, envelope.output());
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut envelope = Envelope::new();
+        // period of 2 quarter frames
+        envelope.write(0b0000_0001);
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(15, envelope.output());
+        envelope.clock();
+        assert_eq!(15, envelope.output());
+        envelope.clock();
+        assert_eq!(14, envelope.output());
+        for _ in 0..28 {
+            envelope.clock();
+        }
+        assert_eq!(0, envelope.output());
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(0, envelope.output());
+    }
+
+    #[test]
+    fn test_loop() {
+        let mut envelope = Envelope::new();
+        envelope.write(0b0010_0000);
+        envelope.restart();
+        for _ in 0..16 {
+            envelope.clock();
+        }
+        assert_eq!(0, envelope.output());
+        envelope.clock();
+        assert_eq!(15, envelope.output());
+    }
+}
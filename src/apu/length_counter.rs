@@ -0,0 +1,90 @@
+// https://www.nesdev.org/wiki/APU_Length_Counter
+#[rustfmt::skip]
+const LENGTHS: [u8; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// Silences a channel once it counts down to 0, it's clocked on every half frame
+pub struct LengthCounter {
+    // cleared through $4015, which also keeps the counter at 0
+    is_enabled: bool,
+    pub is_halted: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    pub fn new() -> Self {
+        LengthCounter {
+            is_enabled: false,
+            is_halted: false,
+            counter: 0,
+        }
+    }
+
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.is_enabled = is_enabled;
+        if !is_enabled {
+            self.counter = 0;
+        }
+    }
+
+    // Index is the top 5 bits of the channel's last register
+    pub fn load(&mut self, index: u8) {
+        if self.is_enabled {
+            self.counter = LENGTHS[(index & 0b1_1111) as usize];
+        }
+    }
+
+    pub fn clock(&mut self) {
+        if !self.is_halted && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        return self.counter > 0;
+    }
+}
+
+#[cfg(test)]
+mod test_length_counter {
+    use super::*;
+
+    #[test]
+    fn test_counts_down_to_zero() {
+        let mut length_counter = LengthCounter::new();
+        length_counter.set_enabled(true);
+        // index 3 is a length of 2
+        length_counter.load(3);
+        length_counter.clock();
+        assert!(length_counter.is_active());
+        length_counter.clock();
+        assert!(!length_counter.is_active());
+        length_counter.clock();
+        assert!(!length_counter.is_active());
+    }
+
+    #[test]
+    fn test_halt() {
+        let mut length_counter = LengthCounter::new();
+        length_counter.set_enabled(true);
+        length_counter.is_halted = true;
+        length_counter.load(3);
+        length_counter.clock();
+        length_counter.clock();
+        assert!(length_counter.is_active());
+    }
+
+    #[test]
+    fn test_disabled_counter_ignores_loads() {
+        let mut length_counter = LengthCounter::new();
+        length_counter.load(1);
+        assert!(!length_counter.is_active());
+
+        length_counter.set_enabled(true);
+        length_counter.load(1);
+        length_counter.set_enabled(false);
+        assert!(!length_counter.is_active());
+    }
+}
@@ -0,0 +1,364 @@
+mod envelope;
+mod length_counter;
+mod noise;
+mod pulse;
+mod triangle;
+
+use crate::region::Region;
+
+use noise::{Noise, NTSC_NOISE_PERIODS, PAL_NOISE_PERIODS};
+use pulse::{Channel, Pulse};
+use triangle::Triangle;
+
+// https://www.nesdev.org/wiki/APU_Frame_Counter
+// CPU cycles after which the frame counter clocks the envelopes, length counters and sweeps.
+// The fourth step ends the sequence in 4-step mode, the fifth one in 5-step mode.
+const NTSC_FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrameCounterMode {
+    FourStep,
+    FiveStep,
+}
+
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    frame_counter_mode: FrameCounterMode,
+    is_frame_interrupt_inhibited: bool,
+    is_frame_interrupt_pending: bool,
+    frame_cycle: u32,
+    // the pulse timers tick on every other CPU cycle
+    is_odd_cycle: bool,
+    pub region: Region,
+    // samples per second of the audio handed out by take_samples
+    pub sample_rate: u32,
+    samples: Vec<f32>,
+    sample_sum: f32,
+    sample_cycles: u32,
+    // sample_rate is added every CPU cycle, a sample is due once it passes the CPU clock rate
+    sample_clock: u32,
+}
+
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            pulse1: Pulse::new(Channel::One),
+            pulse2: Pulse::new(Channel::Two),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            frame_counter_mode: FrameCounterMode::FourStep,
+            is_frame_interrupt_inhibited: false,
+            is_frame_interrupt_pending: false,
+            frame_cycle: 0,
+            is_odd_cycle: false,
+            region: Region::Ntsc,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            samples: vec![],
+            sample_sum: 0.0,
+            sample_cycles: 0,
+            sample_clock: 0,
+        }
+    }
+
+    // https://www.nesdev.org/wiki/CPU_power_up_state, reset silences every channel
+    pub fn reset(&mut self) {
+        self.write_status(0b0000_0000);
+        self.is_frame_interrupt_pending = false;
+        self.frame_cycle = 0;
+    }
+
+    // Runs one CPU cycle
+    pub fn tick(&mut self) {
+        self.triangle.clock_timer();
+        // Dendy keeps the NTSC periods, like the frame counter
+        let noise_periods = match self.region {
+            Region::Pal => &PAL_NOISE_PERIODS,
+            Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIODS,
+        };
+        self.noise.clock_timer(noise_periods);
+        if self.is_odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.is_odd_cycle = !self.is_odd_cycle;
+
+        self.clock_frame_counter();
+        self.mix_sample();
+    }
+
+    pub fn write_register(&mut self, address: u16, data: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulse1.write_register(address - 0x4000, data),
+            0x4004..=0x4007 => self.pulse2.write_register(address - 0x4004, data),
+            0x4008..=0x400B => self.triangle.write_register(address - 0x4008, data),
+            0x400C..=0x400F => self.noise.write_register(address - 0x400C, data),
+            // the DMC channel is not implemented yet
+            0x4010..=0x4013 => (),
+            0x4015 => self.write_status(data),
+            0x4017 => self.write_frame_counter(data),
+            _ => panic!("{:0x} is not an APU register", address),
+        }
+    }
+
+    // https://www.nesdev.org/wiki/APU#Status_($4015)
+    // Reading clears the frame interrupt
+    pub fn read_status(&mut self) -> u8 {
+        let mut status = 0;
+        status |= self.pulse1.length_counter.is_active() as u8;
+        status |= (self.pulse2.length_counter.is_active() as u8) << 1;
+        status |= (self.triangle.length_counter.is_active() as u8) << 2;
+        status |= (self.noise.length_counter.is_active() as u8) << 3;
+        status |= (self.is_frame_interrupt_pending as u8) << 6;
+        self.is_frame_interrupt_pending = false;
+        return status;
+    }
+
+    fn write_status(&mut self, data: u8) {
+        self.pulse1
+            .length_counter
+            .set_enabled(data & 0b0000_0001 != 0);
+        self.pulse2
+            .length_counter
+            .set_enabled(data & 0b0000_0010 != 0);
+        self.triangle
+            .length_counter
+            .set_enabled(data & 0b0000_0100 != 0);
+        self.noise
+            .length_counter
+            .set_enabled(data & 0b0000_1000 != 0);
+    }
+
+    // MI-- ----, writing restarts the sequence. The 5-step mode clocks everything right away.
+    fn write_frame_counter(&mut self, data: u8) {
+        self.frame_counter_mode = match data & 0b1000_0000 {
+            0 => FrameCounterMode::FourStep,
+            _ => FrameCounterMode::FiveStep,
+        };
+        self.is_frame_interrupt_inhibited = data & 0b0100_0000 != 0;
+        if self.is_frame_interrupt_inhibited {
+            self.is_frame_interrupt_pending = false;
+        }
+        self.frame_cycle = 0;
+        if self.frame_counter_mode == FrameCounterMode::FiveStep {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        }
+    }
+
+    // The frame interrupt holds the IRQ line until $4015 is read or it gets inhibited
+    pub fn irq(&self) -> bool {
+        return self.is_frame_interrupt_pending;
+    }
+
+    fn clock_frame_counter(&mut self) {
+        // Dendy keeps the NTSC sequence, in CPU cycles it's close to 60Hz on its slower CPU
+        let steps = match self.region {
+            Region::Pal => PAL_FRAME_STEPS,
+            Region::Ntsc | Region::Dendy => NTSC_FRAME_STEPS,
+        };
+        let last_step = match self.frame_counter_mode {
+            FrameCounterMode::FourStep => 3,
+            FrameCounterMode::FiveStep => 4,
+        };
+
+        self.frame_cycle += 1;
+        let step = match steps.iter().position(|cycle| *cycle == self.frame_cycle) {
+            Some(step) => step,
+            None => return,
+        };
+        match step {
+            0 | 2 => self.clock_quarter_frame(),
+            // the fourth step of the 5-step sequence does nothing
+            3 if last_step == 4 => (),
+            _ => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+        }
+        if step == last_step {
+            if self.frame_counter_mode == FrameCounterMode::FourStep
+                && !self.is_frame_interrupt_inhibited
+            {
+                self.is_frame_interrupt_pending = true;
+            }
+            self.frame_cycle = 0;
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    // https://www.nesdev.org/wiki/APU_Mixer
+    // The channels are mixed non-linearly, the result is between 0.0 and 1.0
+    pub fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_output = match pulse == 0.0 {
+            true => 0.0,
+            false => 95.88 / (8128.0 / pulse + 100.0),
+        };
+
+        let tnd = self.triangle.output() as f32 / 8227.0 + self.noise.output() as f32 / 12241.0;
+        let tnd_output = match tnd == 0.0 {
+            true => 0.0,
+            false => 159.79 / (1.0 / tnd + 100.0),
+        };
+        return pulse_output + tnd_output;
+    }
+
+    // Every sample is the average of the CPU cycles it covers, which filters out the
+    // frequencies the sample rate can't hold
+    fn mix_sample(&mut self) {
+        self.sample_sum += self.output();
+        self.sample_cycles += 1;
+        self.sample_clock += self.sample_rate;
+
+        let cpu_clock_rate = self.region.cpu_clock_rate();
+        if self.sample_clock < cpu_clock_rate {
+            return;
+        }
+        self.sample_clock -= cpu_clock_rate;
+        // nobody is draining the buffer when it holds a whole second
+        if self.samples.len() < self.sample_rate as usize {
+            self.samples
+                .push(self.sample_sum / self.sample_cycles as f32);
+        }
+        self.sample_sum = 0.0;
+        self.sample_cycles = 0;
+    }
+
+    // Returns the samples produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        return std::mem::take(&mut self.samples);
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        return APU::new();
+    }
+}
+
+#[cfg(test)]
+mod test_apu {
+    use super::*;
+
+    fn tick(apu: &mut APU, cycles: u32) {
+        for _ in 0..cycles {
+            apu.tick();
+        }
+    }
+
+    // square wave on the first pulse channel with the shortest length, 10 half frames
+    fn play_pulse1(apu: &mut APU) {
+        apu.write_register(0x4015, 0b0000_0001);
+        apu.write_register(0x4000, 0b1001_1111);
+        apu.write_register(0x4002, 0xFD);
+        apu.write_register(0x4003, 0b0000_0000);
+    }
+
+    #[test]
+    fn test_status_reports_length_counters() {
+        let mut apu = APU::new();
+        play_pulse1(&mut apu);
+        assert_eq!(0b0000_0001, apu.read_status());
+
+        apu.write_register(0x4015, 0b0000_1101);
+        apu.write_register(0x400B, 0b0000_1000);
+        apu.write_register(0x400F, 0b0000_1000);
+        assert_eq!(0b0000_1101, apu.read_status());
+
+        // disabling a channel clears its length counter
+        apu.write_register(0x4015, 0b0000_0100);
+        assert_eq!(0b0000_0100, apu.read_status());
+    }
+
+    #[test]
+    fn test_mixer() {
+        let mut apu = APU::new();
+        // a stopped triangle keeps outputting the step it's on, the top of the wave at power-up
+        let triangle = apu.output();
+        assert!(triangle > 0.0);
+
+        apu.write_register(0x4015, 0b0000_1000);
+        apu.write_register(0x400C, 0b0001_1111);
+        apu.write_register(0x400F, 0b0000_1000);
+        // bit 0 of the shift register starts set, so the noise is silent until it shifts
+        assert_eq!(triangle, apu.output());
+        tick(&mut apu, 4);
+        let mixed = apu.output();
+        assert!(mixed > triangle && mixed <= 1.0);
+    }
+
+    #[test]
+    fn test_frame_counter_clocks_length_counter() {
+        let mut apu = APU::new();
+        play_pulse1(&mut apu);
+        // 2 half frames every 4-step sequence
+        tick(&mut apu, NTSC_FRAME_STEPS[3] * 4);
+        assert_eq!(0b0000_0001, apu.read_status() & 0b0000_0001);
+        tick(&mut apu, NTSC_FRAME_STEPS[3]);
+        assert_eq!(0b0000_0000, apu.read_status() & 0b0000_0001);
+    }
+
+    #[test]
+    fn test_frame_interrupt() {
+        let mut apu = APU::new();
+        tick(&mut apu, NTSC_FRAME_STEPS[3] - 1);
+        assert!(!apu.irq());
+        tick(&mut apu, 1);
+        assert!(apu.irq());
+        assert_eq!(0b0100_0000, apu.read_status());
+        assert!(!apu.irq());
+
+        // inhibited, and never raised in 5-step mode
+        apu.write_register(0x4017, 0b0100_0000);
+        tick(&mut apu, NTSC_FRAME_STEPS[3]);
+        assert!(!apu.irq());
+        apu.write_register(0x4017, 0b1000_0000);
+        tick(&mut apu, NTSC_FRAME_STEPS[4] * 2);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_five_step_mode_clocks_immediately() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0b0000_0001);
+        apu.write_register(0x4003, 0b0001_1000);
+        // length of 2
+        apu.write_register(0x4017, 0b1000_0000);
+        apu.write_register(0x4017, 0b1000_0000);
+        assert_eq!(0b0000_0000, apu.read_status());
+    }
+
+    #[test]
+    fn test_samples() {
+        let mut apu = APU::new();
+        play_pulse1(&mut apu);
+        let cpu_clock_rate = apu.region.cpu_clock_rate();
+        tick(&mut apu, cpu_clock_rate / 10);
+        let samples = apu.take_samples();
+        assert!((4409..=4410).contains(&samples.len()));
+        assert!(samples.iter().any(|sample| *sample > 0.1));
+        assert!(samples
+            .iter()
+            .all(|sample| *sample >= 0.0 && *sample <= 1.0));
+        assert!(apu.take_samples().is_empty());
+    }
+}
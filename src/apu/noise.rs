@@ -0,0 +1,170 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+// https://www.nesdev.org/wiki/APU_Noise
+// Timer periods in CPU cycles
+pub const NTSC_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+pub const PAL_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+pub struct Noise {
+    // 15 bit linear feedback shift register, the channel is silent while bit 0 is set
+    shift_register: u16,
+    // short mode takes the feedback from bit 6 instead of bit 1, which repeats every 93 bits
+    is_short_mode: bool,
+    period_index: u8,
+    timer: u16,
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            shift_register: 1,
+            is_short_mode: false,
+            period_index: 0,
+            timer: 0,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+        }
+    }
+
+    // Register is the offset from $400C, $400D isn't connected
+    pub fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            // --LC VVVV
+            0 => {
+                self.length_counter.is_halted = data & 0b0010_0000 != 0;
+                self.envelope.write(data);
+            }
+            1 => (),
+            // M--- PPPP
+            2 => {
+                self.is_short_mode = data & 0b1000_0000 != 0;
+                self.period_index = data & 0b0000_1111;
+            }
+            // llll l---
+            3 => {
+                self.length_counter.load(data >> 3);
+                self.envelope.restart();
+            }
+            _ => panic!("The noise channel only has 4 registers"),
+        }
+    }
+
+    // Clocked on every CPU cycle, the periods depend on the region
+    pub fn clock_timer(&mut self, periods: &[u16; 16]) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = periods[self.period_index as usize] - 1;
+
+        let tap = match self.is_short_mode {
+            true => 6,
+            false => 1,
+        };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    // 0 to 15
+    pub fn output(&self) -> u8 {
+        if self.shift_register & 1 == 1 || !self.length_counter.is_active() {
+            return 0;
+        }
+        return self.envelope.output();
+    }
+}
+
+#[cfg(test)]
+mod test_noise {
+    use super::*;
+
+    // constant volume 15, the shortest period and a length of 10
+    fn playing_noise(mode: u8) -> Noise {
+        let mut noise = Noise::new();
+        noise.length_counter.set_enabled(true);
+        noise.write_register(0, 0b0001_1111);
+        noise.write_register(2, mode << 7);
+        noise.write_register(3, 0b0000_0000);
+        return noise;
+    }
+
+    // output after every shift of the register
+    fn bits(noise: &mut Noise, count: usize) -> Vec<u8> {
+        let mut result = vec![];
+        for _ in 0..count {
+            for _ in 0..NTSC_NOISE_PERIODS[0] {
+                noise.clock_timer(&NTSC_NOISE_PERIODS);
+            }
+            result.push(noise.output());
+        }
+        return result;
+    }
+
+    // number of shifts until the sequence repeats
+    fn sequence_length(noise: &mut Noise) -> usize {
+        let start = noise.shift_register;
+        let mut length = 0;
+        loop {
+            bits(noise, 1);
+            length += 1;
+            if noise.shift_register == start {
+                return length;
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_mode_sequence() {
+        let mut noise = playing_noise(0);
+        assert_eq!(32767, sequence_length(&mut noise));
+    }
+
+    #[test]
+    fn test_short_mode_sequence() {
+        let mut noise = playing_noise(1);
+        assert_eq!(93, sequence_length(&mut noise));
+    }
+
+    #[test]
+    fn test_output() {
+        let mut noise = playing_noise(0);
+        let output = bits(&mut noise, 100);
+        assert!(output.contains(&15) && output.contains(&0));
+        assert!(output.iter().all(|volume| *volume == 0 || *volume == 15));
+
+        for _ in 0..10 {
+            noise.clock_half_frame();
+        }
+        assert_eq!(vec![0; 100], bits(&mut noise, 100));
+    }
+
+    #[test]
+    fn test_period() {
+        let mut noise = playing_noise(0);
+        // a period of 8 cycles
+        noise.write_register(2, 0b0000_0001);
+        noise.clock_timer(&NTSC_NOISE_PERIODS);
+        let shift_register = noise.shift_register;
+        for _ in 0..7 {
+            noise.clock_timer(&NTSC_NOISE_PERIODS);
+        }
+        assert_eq!(shift_register, noise.shift_register);
+        noise.clock_timer(&NTSC_NOISE_PERIODS);
+        assert_ne!(shift_register, noise.shift_register);
+    }
+}
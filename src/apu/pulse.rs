@@ -0,0 +1,237 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+// https://www.nesdev.org/wiki/APU_Pulse
+// 12.5%, 25%, 50% and 25% negated
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+// The sweep unit of the first channel negates with ones' complement, the second with two's
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    One,
+    Two,
+}
+
+pub struct Pulse {
+    channel: Channel,
+    duty: u8,
+    sequencer_step: u8,
+    timer: u16,
+    timer_period: u16,
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+    // https://www.nesdev.org/wiki/APU_Sweep
+    is_sweep_enabled: bool,
+    sweep_period: u8,
+    is_sweep_negated: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    is_sweep_reload_pending: bool,
+}
+
+impl Pulse {
+    pub fn new(channel: Channel) -> Self {
+        Pulse {
+            channel: channel,
+            duty: 0,
+            sequencer_step: 0,
+            timer: 0,
+            timer_period: 0,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+            is_sweep_enabled: false,
+            sweep_period: 0,
+            is_sweep_negated: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            is_sweep_reload_pending: false,
+        }
+    }
+
+    // Register is the offset from the first register of the channel, $4000 or $4004
+    pub fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            // DDLC VVVV
+            0 => {
+                self.duty = data >> 6;
+                self.length_counter.is_halted = data & 0b0010_0000 != 0;
+                self.envelope.write(data);
+            }
+            // EPPP NSSS
+            1 => {
+                self.is_sweep_enabled = data & 0b1000_0000 != 0;
+                self.sweep_period = (data >> 4) & 0b111;
+                self.is_sweep_negated = data & 0b0000_1000 != 0;
+                self.sweep_shift = data & 0b111;
+                self.is_sweep_reload_pending = true;
+            }
+            // LLLL LLLL
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            // llll lHHH, restarts the duty cycle and the envelope
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length_counter.load(data >> 3);
+                self.sequencer_step = 0;
+                self.envelope.restart();
+            }
+            _ => panic!("Pulse channels only have 4 registers"),
+        }
+    }
+
+    // Clocked on every APU cycle, which is every other CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequencer_step = (self.sequencer_step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+
+        if self.sweep_divider == 0
+            && self.is_sweep_enabled
+            && self.sweep_shift != 0
+            && !self.is_muted()
+        {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.is_sweep_reload_pending {
+            self.sweep_divider = self.sweep_period;
+            self.is_sweep_reload_pending = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    // The period the sweep unit is heading to, it's computed all the time even when it's disabled
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if !self.is_sweep_negated {
+            return self.timer_period + change;
+        }
+        return match self.channel {
+            Channel::One => self.timer_period.saturating_sub(change + 1),
+            Channel::Two => self.timer_period - change,
+        };
+    }
+
+    // Periods below 8 are too high pitched, and targets above $7FF would overflow the timer
+    fn is_muted(&self) -> bool {
+        return self.timer_period < 8 || self.sweep_target() > 0x07FF;
+    }
+
+    // 0 to 15
+    pub fn output(&self) -> u8 {
+        let is_high = DUTY_CYCLES[self.duty as usize][self.sequencer_step as usize] == 1;
+        if !is_high || !self.length_counter.is_active() || self.is_muted() {
+            return 0;
+        }
+        return self.envelope.output();
+    }
+}
+
+#[cfg(test)]
+mod test_pulse {
+    use super::*;
+
+    // constant volume 15, no sweep, a period of 8 and the length counter running
+    fn playing_pulse(channel: Channel, duty: u8) -> Pulse {
+        let mut pulse = Pulse::new(channel);
+        pulse.length_counter.set_enabled(true);
+        pulse.write_register(0, duty << 6 | 0b0001_1111);
+        pulse.write_register(2, 8);
+        // length of 10
+        pulse.write_register(3, 0b0000_0000);
+        return pulse;
+    }
+
+    // output of every step of the duty cycle
+    fn waveform(pulse: &mut Pulse) -> Vec<u8> {
+        let mut result = vec![];
+        for _ in 0..8 {
+            for _ in 0..=pulse.timer_period {
+                pulse.clock_timer();
+            }
+            result.push(pulse.output());
+        }
+        return result;
+    }
+
+    #[test]
+    fn test_duty_cycles() {
+        let mut pulse = playing_pulse(Channel::One, 0);
+        assert_eq!(vec![15, 0, 0, 0, 0, 0, 0, 0], waveform(&mut pulse));
+        let mut pulse = playing_pulse(Channel::One, 2);
+        assert_eq!(vec![15, 15, 15, 15, 0, 0, 0, 0], waveform(&mut pulse));
+        let mut pulse = playing_pulse(Channel::One, 3);
+        assert_eq!(vec![0, 0, 15, 15, 15, 15, 15, 15], waveform(&mut pulse));
+    }
+
+    #[test]
+    fn test_short_periods_are_muted() {
+        let mut pulse = playing_pulse(Channel::One, 3);
+        pulse.write_register(2, 7);
+        assert_eq!(vec![0; 8], waveform(&mut pulse));
+    }
+
+    #[test]
+    fn test_length_counter_silences_channel() {
+        let mut pulse = playing_pulse(Channel::One, 3);
+        for _ in 0..10 {
+            assert!(waveform(&mut pulse).contains(&15));
+            pulse.clock_half_frame();
+        }
+        assert_eq!(vec![0; 8], waveform(&mut pulse));
+    }
+
+    #[test]
+    fn test_sweep_negate_differs_between_channels() {
+        for (channel, expected) in [(Channel::One, 0x0FF), (Channel::Two, 0x100)] {
+            let mut pulse = playing_pulse(channel, 2);
+            pulse.write_register(2, 0x00);
+            pulse.write_register(3, 0b0000_1010);
+            // enabled, period 0, negated, shift 1
+            pulse.write_register(1, 0b1000_1001);
+            pulse.clock_half_frame();
+            assert_eq!(expected, pulse.timer_period);
+        }
+    }
+
+    #[test]
+    fn test_sweep_period() {
+        let mut pulse = playing_pulse(Channel::Two, 2);
+        pulse.write_register(2, 0x00);
+        pulse.write_register(3, 0b0000_1001);
+        // enabled, period 1, shift 2
+        pulse.write_register(1, 0b1001_0010);
+        // the period changes every other half frame
+        pulse.clock_half_frame();
+        assert_eq!(0x140, pulse.timer_period);
+        pulse.clock_half_frame();
+        assert_eq!(0x140, pulse.timer_period);
+        pulse.clock_half_frame();
+        assert_eq!(0x190, pulse.timer_period);
+    }
+
+    #[test]
+    fn test_sweep_overflow_mutes_even_when_disabled() {
+        let mut pulse = playing_pulse(Channel::One, 3);
+        pulse.write_register(2, 0xFF);
+        pulse.write_register(3, 0b0000_1111);
+        // disabled, shift 0, the target is $7FF + $7FF
+        pulse.write_register(1, 0b0000_0000);
+        assert_eq!(vec![0; 8], waveform(&mut pulse));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::apu::length_counter::LengthCounter;
+
+// https://www.nesdev.org/wiki/APU_Triangle
+#[rustfmt::skip]
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15,
+];
+
+pub struct Triangle {
+    sequencer_step: u8,
+    timer: u16,
+    timer_period: u16,
+    pub length_counter: LengthCounter,
+    // https://www.nesdev.org/wiki/APU_Triangle#Linear_counter
+    // a finer grained length counter, clocked on every quarter frame
+    linear_counter: u8,
+    linear_counter_period: u8,
+    is_linear_counter_reload_pending: bool,
+    // halts the length counter and keeps the linear counter reloading
+    is_control_set: bool,
+}
+
+impl Triangle {
+    pub fn new() -> Self {
+        Triangle {
+            sequencer_step: 0,
+            timer: 0,
+            timer_period: 0,
+            length_counter: LengthCounter::new(),
+            linear_counter: 0,
+            linear_counter_period: 0,
+            is_linear_counter_reload_pending: false,
+            is_control_set: false,
+        }
+    }
+
+    // Register is the offset from $4008, $4009 isn't connected
+    pub fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            // CRRR RRRR
+            0 => {
+                self.is_control_set = data & 0b1000_0000 != 0;
+                self.length_counter.is_halted = self.is_control_set;
+                self.linear_counter_period = data & 0b0111_1111;
+            }
+            1 => (),
+            // LLLL LLLL
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            // llll lHHH
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length_counter.load(data >> 3);
+                self.is_linear_counter_reload_pending = true;
+            }
+            _ => panic!("The triangle channel only has 4 registers"),
+        }
+    }
+
+    // Clocked on every CPU cycle, twice as fast as the other channels, so it's an octave lower
+    // than a pulse with the same period. The wave stops where it is once either counter runs out.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+        if self.linear_counter > 0 && self.length_counter.is_active() {
+            self.sequencer_step = (self.sequencer_step + 1) % 32;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.is_linear_counter_reload_pending {
+            self.linear_counter = self.linear_counter_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.is_control_set {
+            self.is_linear_counter_reload_pending = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    // 0 to 15
+    pub fn output(&self) -> u8 {
+        return SEQUENCE[self.sequencer_step as usize];
+    }
+}
+
+#[cfg(test)]
+mod test_triangle {
+    use super::*;
+
+    // period of 2, linear counter of 3 quarter frames and a length of 10
+    fn playing_triangle() -> Triangle {
+        let mut triangle = Triangle::new();
+        triangle.length_counter.set_enabled(true);
+        triangle.write_register(0, 0b0000_0011);
+        triangle.write_register(2, 2);
+        triangle.write_register(3, 0b0000_0000);
+        triangle.clock_quarter_frame();
+        return triangle;
+    }
+
+    fn steps(triangle: &mut Triangle, count: usize) -> Vec<u8> {
+        let mut result = vec![];
+        for _ in 0..count {
+            for _ in 0..=triangle.timer_period {
+                triangle.clock_timer();
+            }
+            result.push(triangle.output());
+        }
+        return result;
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut triangle = playing_triangle();
+        assert_eq!(15, triangle.output());
+        let wave = steps(&mut triangle, 32);
+        assert_eq!(vec![14, 13, 12], wave[0..3]);
+        assert_eq!(vec![1, 0, 0, 1, 2], wave[13..18]);
+        assert_eq!(15, wave[31]);
+    }
+
+    #[test]
+    fn test_linear_counter_stops_sequencer() {
+        let mut triangle = playing_triangle();
+        for _ in 0..3 {
+            triangle.clock_quarter_frame();
+        }
+        let output = triangle.output();
+        assert_eq!(vec![output; 4], steps(&mut triangle, 4));
+    }
+
+    #[test]
+    fn test_control_flag_keeps_reloading_linear_counter() {
+        let mut triangle = playing_triangle();
+        triangle.write_register(0, 0b1000_0011);
+        triangle.write_register(3, 0b0000_0000);
+        for _ in 0..10 {
+            triangle.clock_quarter_frame();
+            triangle.clock_half_frame();
+        }
+        assert_ne!(vec![triangle.output(); 4], steps(&mut triangle, 4));
+    }
+
+    #[test]
+    fn test_length_counter_stops_sequencer() {
+        let mut triangle = playing_triangle();
+        for _ in 0..10 {
+            triangle.clock_half_frame();
+        }
+        let output = triangle.output();
+        assert_eq!(vec![output; 4], steps(&mut triangle, 4));
+    }
+}
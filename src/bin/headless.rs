@@ -0,0 +1,629 @@
+// Runs a ROM without a display, for CI screenshot and RAM assertion tests.
+//
+// usage: headless <rom.nes> [--frames N] [--input FRAME:BUTTONS]... [--screenshot PATH] [--ram-dump PATH] [--region ntsc|pal|dendy] [--palette PATH] [--ntsc] [--scale SCALER:N] [--crt]
+//
+// --input sets the controller state from the given frame onwards. Buttons are
+// separated by '+', e.g. `--input 30:START --input 40:A+RIGHT --input 50:`.
+// Screenshots are written as PNG when PATH ends in .png, otherwise as PPM.
+// The RAM dump is written to stdout when no path is given.
+// --region overrides the region from the ROM header.
+// --palette loads the colors from a 192 or 1536 byte .pal file.
+// --ntsc runs the screenshot through the NTSC filter, which makes it 512 pixels wide.
+// --scale upscales the screenshot 2, 3 or 4 times with nearest, scalenx or xbr, e.g. `--scale xbr:3`.
+// --crt adds scanlines and an aperture grille to the screenshot.
+use vines::controller::Controller;
+use vines::nes::Nes;
+use vines::ppu::filters::crt::{aperture_grille, scanlines};
+use vines::ppu::filters::ntsc::{NtscFilter, NTSC_WIDTH};
+use vines::ppu::filters::scale::{scale, Scaler};
+use vines::ppu::filters::Image;
+use vines::ppu::palette::Palette;
+use vines::region::Region;
+use vines::rom::Rom;
+
+use std::fs::{read, write};
+use std::io::{stdout, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::process::ExitCode;
+
+const HEIGHT: usize = 240;
+const CRT_SCANLINE_INTENSITY: f64 = 0.4;
+const CRT_GRILLE_INTENSITY: f64 = 0.2;
+
+struct Options {
+    rom_path: String,
+    frames: usize,
+    inputs: Vec<(usize, u8)>,
+    screenshot_path: Option<String>,
+    ram_dump_path: Option<String>,
+    region: Option<Region>,
+    palette_path: Option<String>,
+    is_ntsc_filter_enabled: bool,
+    scale: Option<(Scaler, usize)>,
+    is_crt_enabled: bool,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}", error);
+            eprintln!("usage: headless <rom.nes> [--frames N] [--input FRAME:BUTTONS]... [--screenshot PATH] [--ram-dump PATH] [--region ntsc|pal|dendy] [--palette PATH] [--ntsc] [--scale SCALER:N] [--crt]");
+            return ExitCode::from(2);
+        }
+    };
+    return run(&options, &mut stdout().lock());
+}
+
+// Runs the ROM and writes the RAM dump to `output` when no dump path is given
+fn run(options: &Options, output: &mut dyn Write) -> ExitCode {
+    let rom_bytes = match read(&options.rom_path) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            eprintln!("Could not read {}: {}", options.rom_path, error);
+            return ExitCode::from(2);
+        }
+    };
+    let mut rom = match Rom::try_from(&rom_bytes) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("Could not load {}: {}", options.rom_path, error);
+            return ExitCode::from(2);
+        }
+    };
+
+    if let Some(region) = options.region {
+        rom.region = region;
+    }
+
+    let mut nes = Nes::new(rom);
+    if let Some(path) = &options.palette_path {
+        let palette = match read(path).map_err(|error| error.to_string()) {
+            Ok(bytes) => Palette::try_from(&bytes),
+            Err(error) => Err(error),
+        };
+        match palette {
+            Ok(palette) => nes.ppu().borrow_mut().palette = palette,
+            Err(error) => {
+                eprintln!("Could not load {}: {}", path, error);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    // a panic here means the CPU ran into an opcode it couldn't decode,
+    // or the program touched something the emulator doesn't support
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let controller = nes.controller();
+        for frame in 0..options.frames {
+            for (_, buttons) in options.inputs.iter().filter(|(start, _)| *start == frame) {
+                set_buttons(&mut controller.borrow_mut(), *buttons);
+            }
+            nes.run_frame();
+        }
+    }));
+    if result.is_err() {
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(path) = &options.screenshot_path {
+        let frame = nes.frame();
+        let mut image = match (options.is_ntsc_filter_enabled, options.scale) {
+            (true, _) => {
+                let frame_count = nes.ppu().borrow().frame_count;
+                Image {
+                    width: NTSC_WIDTH,
+                    height: HEIGHT,
+                    scale: 1,
+                    bytes: NtscFilter::new().apply(&frame, frame_count),
+                }
+            }
+            (false, Some((scaler, factor))) => scale(&frame, scaler, factor),
+            (false, None) => Image::from_frame(&frame),
+        };
+        if options.is_crt_enabled {
+            scanlines(&mut image, CRT_SCANLINE_INTENSITY);
+            aperture_grille(&mut image, CRT_GRILLE_INTENSITY);
+        }
+        let encoded = if path.ends_with(".png") {
+            encode_png(&image)
+        } else {
+            encode_ppm(&image)
+        };
+        if let Err(error) = write(path, encoded) {
+            eprintln!("Could not write {}: {}", path, error);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let ram_dump = hex_dump(nes.mapper().borrow().ram());
+    match &options.ram_dump_path {
+        Some(path) => {
+            if let Err(error) = write(path, ram_dump) {
+                eprintln!("Could not write {}: {}", path, error);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => {
+            if let Err(error) = output.write_all(ram_dump.as_bytes()) {
+                eprintln!("Could not write the RAM dump: {}", error);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    return ExitCode::SUCCESS;
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom_path: String::new(),
+        frames: 60,
+        inputs: vec![],
+        screenshot_path: None,
+        ram_dump_path: None,
+        region: None,
+        palette_path: None,
+        is_ntsc_filter_enabled: false,
+        scale: None,
+        is_crt_enabled: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                let value = args.next().ok_or("--frames needs a value")?;
+                options.frames = value
+                    .parse()
+                    .map_err(|_| format!("Invalid frame count {}", value))?;
+            }
+            "--input" => {
+                let value = args.next().ok_or("--input needs a value")?;
+                options.inputs.push(parse_input(value)?);
+            }
+            "--screenshot" => {
+                let value = args.next().ok_or("--screenshot needs a value")?;
+                options.screenshot_path = Some(value.clone());
+            }
+            "--ram-dump" => {
+                let value = args.next().ok_or("--ram-dump needs a value")?;
+                options.ram_dump_path = Some(value.clone());
+            }
+            "--region" => {
+                let value = args.next().ok_or("--region needs a value")?;
+                options.region = Some(match value.as_str() {
+                    "ntsc" => Region::Ntsc,
+                    "pal" => Region::Pal,
+                    "dendy" => Region::Dendy,
+                    _ => return Err(format!("Unknown region {}", value)),
+                });
+            }
+            "--palette" => {
+                let value = args.next().ok_or("--palette needs a value")?;
+                options.palette_path = Some(value.clone());
+            }
+            "--ntsc" => options.is_ntsc_filter_enabled = true,
+            "--scale" => {
+                let value = args.next().ok_or("--scale needs a value")?;
+                options.scale = Some(parse_scale(value)?);
+            }
+            "--crt" => options.is_crt_enabled = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => options.rom_path = arg.clone(),
+        }
+    }
+
+    if options.rom_path.is_empty() {
+        return Err("Missing ROM path".to_string());
+    }
+    if options.is_ntsc_filter_enabled && options.scale.is_some() {
+        return Err("--ntsc can't be combined with --scale".to_string());
+    }
+    return Ok(options);
+}
+
+fn parse_scale(value: &str) -> Result<(Scaler, usize), String> {
+    let (scaler, factor) = value
+        .split_once(':')
+        .ok_or(format!("Invalid scale {}, expected SCALER:N", value))?;
+    let scaler = match scaler {
+        "nearest" => Scaler::Nearest,
+        "scalenx" => Scaler::ScaleNx,
+        "xbr" => Scaler::Xbr,
+        _ => return Err(format!("Unknown scaler {}", scaler)),
+    };
+    let factor = match factor.parse() {
+        Ok(factor @ 2..=4) => factor,
+        _ => {
+            return Err(format!(
+                "Invalid scale factor {}, expected 2, 3 or 4",
+                factor
+            ))
+        }
+    };
+    return Ok((scaler, factor));
+}
+
+fn parse_input(value: &str) -> Result<(usize, u8), String> {
+    let (frame, buttons) = value
+        .split_once(':')
+        .ok_or(format!("Invalid input {}, expected FRAME:BUTTONS", value))?;
+    let frame = frame
+        .parse()
+        .map_err(|_| format!("Invalid frame {}", frame))?;
+
+    let mut status = 0;
+    for button in buttons.split('+').filter(|button| !button.is_empty()) {
+        status |= match button.to_uppercase().as_str() {
+            "RIGHT" => Controller::RIGHT,
+            "LEFT" => Controller::LEFT,
+            "DOWN" => Controller::DOWN,
+            "UP" => Controller::UP,
+            "START" => Controller::START,
+            "SELECT" => Controller::SELECT,
+            "B" => Controller::BUTTON_B,
+            "A" => Controller::BUTTON_A,
+            _ => return Err(format!("Unknown button {}", button)),
+        };
+    }
+    return Ok((frame, status));
+}
+
+fn set_buttons(controller: &mut Controller, buttons: u8) {
+    controller.release_button(0b1111_1111);
+    controller.press_button(buttons);
+}
+
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for (row, chunk) in bytes.chunks(16).enumerate() {
+        result.push_str(&format!("{:04x}:", row * 16));
+        for byte in chunk {
+            result.push_str(&format!(" {:02x}", byte));
+        }
+        result.push('\n');
+    }
+    return result;
+}
+
+fn encode_ppm(image: &Image) -> Vec<u8> {
+    let mut result = format!("P6\n{} {}\n255\n", image.width, image.height).into_bytes();
+    result.extend_from_slice(&image.bytes);
+    return result;
+}
+
+// Minimal PNG encoder, the image data is stored in uncompressed deflate blocks
+fn encode_png(image: &Image) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(image.height * (image.width * 3 + 1));
+    for row in image.bytes.chunks(image.width * 3) {
+        raw.push(0); // no filter
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(0xFFFF).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        let is_last = i == blocks.len() - 1;
+        let length = block.len() as u16;
+        zlib.push(is_last as u8);
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend_from_slice(&(image.width as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bit depth, RGB, no interlace
+
+    let mut result = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    png_chunk(&mut result, b"IHDR", &header);
+    png_chunk(&mut result, b"IDAT", &zlib);
+    png_chunk(&mut result, b"IEND", &[]);
+    return result;
+}
+
+fn png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    return !crc;
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    return (b << 16) | a;
+}
+
+#[cfg(test)]
+mod test_headless {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        return args.iter().map(|arg| arg.to_string()).collect();
+    }
+
+    // NROM image with the reset vector pointing at `program`
+    fn write_rom(name: &str, program: &[u8]) -> String {
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut prg_rom = vec![0xEA; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        prg_rom[0x3FFC..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80]);
+        rom.extend_from_slice(&prg_rom);
+        rom.extend_from_slice(&[0; 0x2000]);
+
+        let path = std::env::temp_dir().join(format!("vines_headless_{}.nes", name));
+        write(&path, rom).unwrap();
+        return path.to_string_lossy().to_string();
+    }
+
+    // Undoes encode_png, returns the width, height and pixels
+    fn decode_png(png: &[u8]) -> (usize, usize, Vec<u8>) {
+        assert_eq!(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A], &png[..8]);
+        let mut header = vec![];
+        let mut zlib = vec![];
+        let mut position = 8;
+        while position < png.len() {
+            let length = u32::from_be_bytes(png[position..position + 4].try_into().unwrap());
+            let chunk = &png[position + 4..position + 8 + length as usize];
+            let crc = &png[position + 8 + length as usize..position + 12 + length as usize];
+            assert_eq!(crc32(chunk).to_be_bytes(), crc);
+            match &chunk[..4] {
+                b"IHDR" => header.extend_from_slice(&chunk[4..]),
+                b"IDAT" => zlib.extend_from_slice(&chunk[4..]),
+                _ => (),
+            }
+            position += 12 + length as usize;
+        }
+        let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+
+        let mut raw = vec![];
+        let mut position = 2;
+        loop {
+            let is_last = zlib[position] == 1;
+            let length = u16::from_le_bytes([zlib[position + 1], zlib[position + 2]]);
+            let inverted_length = u16::from_le_bytes([zlib[position + 3], zlib[position + 4]]);
+            assert_eq!(!length, inverted_length);
+            raw.extend_from_slice(&zlib[position + 5..position + 5 + length as usize]);
+            position += 5 + length as usize;
+            if is_last {
+                break;
+            }
+        }
+        assert_eq!(adler32(&raw).to_be_bytes(), zlib[position..]);
+
+        let mut pixels = vec![];
+        for row in raw.chunks(width * 3 + 1) {
+            assert_eq!(0, row[0]);
+            pixels.extend_from_slice(&row[1..]);
+        }
+        return (width, height, pixels);
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = parse_options(&args(&[
+            "game.nes",
+            "--frames",
+            "10",
+            "--input",
+            "5:A+right",
+            "--screenshot",
+            "out.png",
+            "--ram-dump",
+            "ram.txt",
+            "--region",
+            "pal",
+            "--palette",
+            "custom.pal",
+            "--scale",
+            "xbr:3",
+            "--crt",
+        ]))
+        .unwrap();
+        assert_eq!("game.nes", options.rom_path);
+        assert_eq!(10, options.frames);
+        assert_eq!(
+            vec![(5, Controller::BUTTON_A | Controller::RIGHT)],
+            options.inputs
+        );
+        assert_eq!(Some("out.png".to_string()), options.screenshot_path);
+        assert_eq!(Some("ram.txt".to_string()), options.ram_dump_path);
+        assert_eq!(Some(Region::Pal), options.region);
+        assert_eq!(Some("custom.pal".to_string()), options.palette_path);
+        assert_eq!(Some((Scaler::Xbr, 3)), options.scale);
+        assert!(options.is_crt_enabled);
+        assert!(!options.is_ntsc_filter_enabled);
+    }
+
+    #[test]
+    fn test_parse_options_defaults() {
+        let options = parse_options(&args(&["game.nes"])).unwrap();
+        assert_eq!(60, options.frames);
+        assert!(options.inputs.is_empty());
+        assert_eq!(None, options.screenshot_path);
+        assert_eq!(None, options.ram_dump_path);
+        assert_eq!(None, options.region);
+        assert_eq!(None, options.palette_path);
+        assert!(!options.is_ntsc_filter_enabled);
+        assert_eq!(None, options.scale);
+        assert!(!options.is_crt_enabled);
+    }
+
+    #[test]
+    fn test_parse_options_errors() {
+        let error = |arguments: &[&str]| parse_options(&args(arguments)).err();
+        assert_eq!(
+            Some("Missing ROM path".to_string()),
+            error(&["--frames", "1"])
+        );
+        assert_eq!(
+            Some("--frames needs a value".to_string()),
+            error(&["game.nes", "--frames"])
+        );
+        assert_eq!(
+            Some("Invalid frame count x".to_string()),
+            error(&["game.nes", "--frames", "x"])
+        );
+        assert_eq!(
+            Some("Unknown option --fast".to_string()),
+            error(&["game.nes", "--fast"])
+        );
+        assert_eq!(
+            Some("Unknown region secam".to_string()),
+            error(&["game.nes", "--region", "secam"])
+        );
+        assert_eq!(
+            Some("Unknown scaler hq".to_string()),
+            error(&["game.nes", "--scale", "hq:2"])
+        );
+        assert_eq!(
+            Some("Invalid scale factor 5, expected 2, 3 or 4".to_string()),
+            error(&["game.nes", "--scale", "nearest:5"])
+        );
+        assert_eq!(
+            Some("--ntsc can't be combined with --scale".to_string()),
+            error(&["game.nes", "--ntsc", "--scale", "nearest:2"])
+        );
+    }
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(Ok((30, Controller::START)), parse_input("30:START"));
+        assert_eq!(
+            Ok((40, Controller::BUTTON_A | Controller::LEFT)),
+            parse_input("40:a+Left")
+        );
+        // no buttons releases everything
+        assert_eq!(Ok((50, 0)), parse_input("50:"));
+    }
+
+    #[test]
+    fn test_parse_input_errors() {
+        assert_eq!(
+            Err("Invalid input 30, expected FRAME:BUTTONS".to_string()),
+            parse_input("30")
+        );
+        assert_eq!(Err("Invalid frame x".to_string()), parse_input("x:A"));
+        assert_eq!(
+            Err("Unknown button JUMP".to_string()),
+            parse_input("1:A+JUMP")
+        );
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let bytes: Vec<u8> = (0..18).collect();
+        assert_eq!(
+            "0000: 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f\n0010: 10 11\n",
+            hex_dump(&bytes)
+        );
+        assert_eq!("", hex_dump(&[]));
+    }
+
+    #[test]
+    fn test_encode_ppm() {
+        let image = Image {
+            width: 2,
+            height: 1,
+            scale: 1,
+            bytes: vec![1, 2, 3, 4, 5, 6],
+        };
+        assert_eq!(
+            b"P6\n2 1\n255\n\x01\x02\x03\x04\x05\x06".to_vec(),
+            encode_ppm(&image)
+        );
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let image = Image {
+            width: 1,
+            height: 1,
+            scale: 1,
+            bytes: vec![0xFF, 0x00, 0x80],
+        };
+        let png = encode_png(&image);
+        // IEND has no data, so its CRC is always the same
+        assert_eq!(
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82],
+            png[png.len() - 12..]
+        );
+        assert_eq!((1, 1, image.bytes.clone()), decode_png(&png));
+    }
+
+    #[test]
+    fn test_encode_png_round_trip() {
+        // more than 0xFFFF bytes of image data, so it's split into several deflate blocks
+        let mut image = Image::new(256, HEIGHT, 1);
+        image.bytes = (0..image.bytes.len())
+            .map(|i| (i * 7 % 251) as u8)
+            .collect();
+        let png = encode_png(&image);
+        assert_eq!((256, HEIGHT, image.bytes.clone()), decode_png(&png));
+    }
+
+    #[test]
+    fn test_run_writes_ram_dump() {
+        let rom_path = write_rom(
+            "ram_dump",
+            &[
+                0xA9, 0x42, // LDA #$42
+                0x85, 0x01, // STA $01
+                0x4C, 0x04, 0x80, // JMP $8004
+            ],
+        );
+        let options = parse_options(&args(&[&rom_path, "--frames", "1"])).unwrap();
+        let mut output = vec![];
+        assert_eq!(ExitCode::SUCCESS, run(&options, &mut output));
+        let dump = String::from_utf8(output).unwrap();
+        assert!(dump.starts_with("0000: 00 42 00"));
+        assert_eq!(0x800 / 16, dump.lines().count());
+    }
+
+    #[test]
+    fn test_run_fails_on_panic() {
+        // $02 isn't a valid opcode, the CPU panics when it tries to decode it
+        let rom_path = write_rom("panic", &[0x02]);
+        let options = parse_options(&args(&[&rom_path, "--frames", "1"])).unwrap();
+        let mut output = vec![];
+        assert_eq!(ExitCode::FAILURE, run(&options, &mut output));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_run_fails_on_invalid_palette() {
+        let rom_path = write_rom("invalid_palette", &[0x4C, 0x00, 0x80]);
+        let palette_path = std::env::temp_dir().join("vines_headless_invalid.pal");
+        write(&palette_path, [0; 10]).unwrap();
+        let palette_path = palette_path.to_string_lossy().to_string();
+        let options = parse_options(&args(&[&rom_path, "--palette", &palette_path])).unwrap();
+        let mut output = vec![];
+        assert_eq!(ExitCode::from(2), run(&options, &mut output));
+        assert!(output.is_empty());
+    }
+}
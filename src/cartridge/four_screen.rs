@@ -0,0 +1,82 @@
+use crate::cartridge::*;
+
+// https://www.nesdev.org/wiki/Mirroring#4-Screen
+// Four screen boards carry their own VRAM so that all four nametables are
+// unique. The board it wraps keeps handling everything else.
+pub struct FourScreen<C: Cartridge> {
+    board: C,
+    vram: [u8; 0x1000],
+}
+
+impl<C: Cartridge> FourScreen<C> {
+    pub fn new(board: C) -> Self {
+        FourScreen {
+            board: board,
+            vram: [0; 0x1000],
+        }
+    }
+}
+
+impl<C: Cartridge> Cartridge for FourScreen<C> {
+    fn cpu_read(&self, address: u16) -> u8 {
+        return self.board.cpu_read(address);
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        self.board.cpu_write(address, data);
+    }
+
+    fn ppu_read(&self, address: u16) -> u8 {
+        return self.board.ppu_read(address);
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        self.board.ppu_write(address, data);
+    }
+
+    // mirroring registers of the wrapped board have no effect
+    fn mirroring(&self) -> Mirroring {
+        return Mirroring::FourScreen;
+    }
+
+    fn nametable_read(&self, address: u16, _vram: &[u8; 2048]) -> u8 {
+        return self.vram[(address & 0x0FFF) as usize];
+    }
+
+    fn nametable_write(&mut self, address: u16, data: u8, _vram: &mut [u8; 2048]) {
+        self.vram[(address & 0x0FFF) as usize] = data;
+    }
+
+    fn irq(&self) -> bool {
+        return self.board.irq();
+    }
+
+    fn notify_ppu_fetch(&mut self, address: u16) {
+        self.board.notify_ppu_fetch(address);
+    }
+}
+
+#[cfg(test)]
+mod test_four_screen {
+    use super::*;
+    use crate::cartridge::mmc3::Mmc3;
+
+    #[test]
+    fn test_all_nametables_are_unique() {
+        let rom = Rom::new(vec![0; 0x8000], vec![], 4, Mirroring::FourScreen);
+        let mut four_screen = FourScreen::new(Mmc3::new(rom));
+        let mut vram = [0; 2048];
+        for nametable in 0..4 {
+            four_screen.nametable_write(0x2000 + nametable * 0x400, nametable as u8 + 1, &mut vram);
+        }
+        // switching to horizontal mirroring on the MMC3 is ignored
+        four_screen.cpu_write(0xA000, 1);
+        for nametable in 0..4 {
+            assert_eq!(
+                nametable as u8 + 1,
+                four_screen.nametable_read(0x2000 + nametable * 0x400, &vram)
+            );
+        }
+        assert_eq!([0; 2048], vram);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::cartridge::*;
+
+// https://www.nesdev.org/wiki/MMC1
+const PRG_RAM_START: u16 = 0x6000;
+const PRG_RAM_END: u16 = 0x7FFF;
+const PRG_ROM_START: u16 = 0x8000;
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+    shift_register: u8,
+    write_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+}
+
+impl Mmc1 {
+    pub fn new(rom: Rom) -> Self {
+        let (chr, is_chr_ram) = chr_or_chr_ram(&rom);
+        Mmc1 {
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+            chr: chr,
+            is_chr_ram: is_chr_ram,
+            shift_register: 0,
+            write_count: 0,
+            // the last bank is fixed at $C000 on power up
+            control: 0b0_11_00,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        if data & 0b1000_0000 != 0 {
+            self.shift_register = 0;
+            self.write_count = 0;
+            self.control = self.control | 0b0_11_00;
+            return;
+        }
+
+        // bits arrive lsb first, the fifth write copies the value to the register selected by bits 13-14
+        self.shift_register = (self.shift_register >> 1) | ((data & 1) << 4);
+        self.write_count += 1;
+        if self.write_count < 5 {
+            return;
+        }
+
+        match (address >> 13) & 0b11 {
+            0 => self.control = self.shift_register,
+            1 => self.chr_bank_0 = self.shift_register,
+            2 => self.chr_bank_1 = self.shift_register,
+            3 => self.prg_bank = self.shift_register,
+            _ => panic!("Impossible!"),
+        }
+        self.shift_register = 0;
+        self.write_count = 0;
+    }
+
+    fn is_prg_ram_enabled(&self) -> bool {
+        return self.prg_bank & 0b1_0000 == 0;
+    }
+
+    fn prg_rom_address(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        // SUROM boards use bit 4 of the CHR bank register to select the 256KB PRG half
+        let outer_bank = if bank_count > 16 {
+            (self.chr_bank_0 & 0b1_0000) as usize
+        } else {
+            0
+        };
+        let last_bank = bank_count.min(16) - 1;
+        let bank = (self.prg_bank & 0b1111) as usize;
+        let is_upper_half = address >= 0xC000;
+
+        let selected_bank = match ((self.control >> 2) & 0b11, is_upper_half) {
+            // 32KB mode, the low bit of the bank number is ignored
+            (0 | 1, false) => bank & 0b1110,
+            (0 | 1, true) => bank | 0b0001,
+            // first bank fixed at $8000
+            (2, false) => 0,
+            (2, true) => bank,
+            // last bank fixed at $C000
+            (3, false) => bank,
+            (3, true) => last_bank,
+            _ => panic!("Impossible!"),
+        };
+
+        let offset = (address as usize - PRG_ROM_START as usize) % PRG_BANK_SIZE;
+        return (((outer_bank | selected_bank) % bank_count) * PRG_BANK_SIZE + offset)
+            % self.prg_rom.len();
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        let bank = match (self.control & 0b1_0000 != 0, address >= 0x1000) {
+            // 8KB mode, the low bit of the bank number is ignored
+            (false, false) => self.chr_bank_0 & 0b1_1110,
+            (false, true) => self.chr_bank_0 | 0b0_0001,
+            (true, false) => self.chr_bank_0,
+            (true, true) => self.chr_bank_1,
+        } as usize;
+        let offset = address as usize % CHR_BANK_SIZE;
+        return (bank * CHR_BANK_SIZE + offset) % self.chr.len();
+    }
+}
+
+impl Cartridge for Mmc1 {
+    fn cpu_read(&self, address: u16) -> u8 {
+        match address {
+            PRG_RAM_START..=PRG_RAM_END => {
+                if self.is_prg_ram_enabled() {
+                    return self.prg_ram[(address - PRG_RAM_START) as usize];
+                }
+                return 0;
+            }
+            PRG_ROM_START..=0xFFFF => self.prg_rom[self.prg_rom_address(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            PRG_RAM_START..=PRG_RAM_END if self.is_prg_ram_enabled() => {
+                self.prg_ram[(address - PRG_RAM_START) as usize] = data;
+            }
+            PRG_ROM_START..=0xFFFF => self.write_register(address, data),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> u8 {
+        return self.chr[self.chr_address(address)];
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.is_chr_ram {
+            let chr_address = self.chr_address(address);
+            self.chr[chr_address] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            3 => Mirroring::Horizontal,
+            _ => panic!("Impossible!"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_mmc1 {
+    use super::*;
+    use test_case::test_case;
+
+    // every 16KB PRG bank and 4KB CHR bank is filled with its own number
+    fn test_mmc1(prg_banks: usize, chr_banks: usize) -> Mmc1 {
+        let prg_rom = (0..prg_banks)
+            .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+            .collect();
+        let chr_rom = (0..chr_banks)
+            .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+            .collect();
+        return Mmc1::new(Rom::new(prg_rom, chr_rom, 1, Mirroring::Horizontal));
+    }
+
+    fn serial_write(mmc1: &mut Mmc1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mmc1.cpu_write(address, (value >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn test_power_up_state_fixes_last_bank() {
+        let mmc1 = test_mmc1(8, 2);
+        assert_eq!(0, mmc1.cpu_read(0x8000));
+        assert_eq!(7, mmc1.cpu_read(0xC000));
+    }
+
+    #[test]
+    fn test_serial_write_loads_register_after_fifth_write() {
+        let mut mmc1 = test_mmc1(8, 2);
+        for _ in 0..4 {
+            mmc1.cpu_write(0xE000, 1);
+        }
+        assert_eq!(0, mmc1.cpu_read(0x8000));
+        mmc1.cpu_write(0xE000, 0);
+        // bank 0b01111 wraps around the 8 available banks
+        assert_eq!(7, mmc1.cpu_read(0x8000));
+    }
+
+    #[test]
+    fn test_reset_bit_clears_shift_register() {
+        let mut mmc1 = test_mmc1(8, 2);
+        mmc1.cpu_write(0xE000, 1);
+        mmc1.cpu_write(0xE000, 1);
+        mmc1.cpu_write(0x8000, 0b1000_0000);
+        serial_write(&mut mmc1, 0xE000, 3);
+        assert_eq!(3, mmc1.cpu_read(0x8000));
+    }
+
+    #[test_case(0b0_00_00, 4, 5)]
+    #[test_case(0b0_01_00, 4, 5)]
+    #[test_case(0b0_10_00, 0, 5)]
+    #[test_case(0b0_11_00, 5, 7)]
+    fn test_prg_bank_modes(control: u8, expected_lower: u8, expected_upper: u8) {
+        let mut mmc1 = test_mmc1(8, 2);
+        serial_write(&mut mmc1, 0x8000, control);
+        serial_write(&mut mmc1, 0xE000, 5);
+        assert_eq!(expected_lower, mmc1.cpu_read(0x8000));
+        assert_eq!(expected_upper, mmc1.cpu_read(0xC000));
+    }
+
+    #[test_case(0b0_0000, 3, 2, 3)]
+    #[test_case(0b1_0000, 3, 3, 1)]
+    fn test_chr_bank_modes(control: u8, chr_bank_0: u8, expected_lower: u8, expected_upper: u8) {
+        let mut mmc1 = test_mmc1(2, 4);
+        serial_write(&mut mmc1, 0x8000, control);
+        serial_write(&mut mmc1, 0xA000, chr_bank_0);
+        serial_write(&mut mmc1, 0xC000, 1);
+        assert_eq!(expected_lower, mmc1.ppu_read(0x0000));
+        assert_eq!(expected_upper, mmc1.ppu_read(0x1000));
+    }
+
+    #[test_case(0, Mirroring::SingleScreenLower)]
+    #[test_case(1, Mirroring::SingleScreenUpper)]
+    #[test_case(2, Mirroring::Vertical)]
+    #[test_case(3, Mirroring::Horizontal)]
+    fn test_mirroring(control: u8, expected: Mirroring) {
+        let mut mmc1 = test_mmc1(2, 2);
+        serial_write(&mut mmc1, 0x8000, control);
+        assert_eq!(expected, mmc1.mirroring());
+    }
+
+    #[test]
+    fn test_prg_ram() {
+        let mut mmc1 = test_mmc1(2, 2);
+        mmc1.cpu_write(0x6123, 0xAA);
+        assert_eq!(0xAA, mmc1.cpu_read(0x6123));
+
+        serial_write(&mut mmc1, 0xE000, 0b1_0000);
+        assert_eq!(0x00, mmc1.cpu_read(0x6123));
+        mmc1.cpu_write(0x6123, 0xBB);
+
+        serial_write(&mut mmc1, 0xE000, 0b0_0000);
+        assert_eq!(0xAA, mmc1.cpu_read(0x6123));
+    }
+
+    #[test]
+    fn test_surom_selects_prg_half_with_chr_bank() {
+        let mut mmc1 = test_mmc1(32, 0);
+        assert_eq!(15, mmc1.cpu_read(0xC000));
+        serial_write(&mut mmc1, 0xA000, 0b1_0000);
+        assert_eq!(16, mmc1.cpu_read(0x8000));
+        assert_eq!(31, mmc1.cpu_read(0xC000));
+    }
+}
@@ -0,0 +1,363 @@
+use crate::cartridge::*;
+
+// https://www.nesdev.org/wiki/MMC3
+const PRG_RAM_START: u16 = 0x6000;
+const PRG_RAM_END: u16 = 0x7FFF;
+const PRG_ROM_START: u16 = 0x8000;
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+// A12 has to stay low for a few fetches before a rising edge clocks the IRQ counter
+const A12_LOW_FETCHES: u8 = 3;
+
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+    bank_select: u8,
+    bank_registers: [u8; 8],
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    prg_ram_write_protected: bool,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12_low_fetches: u8,
+}
+
+impl Mmc3 {
+    pub fn new(rom: Rom) -> Self {
+        let (chr, is_chr_ram) = chr_or_chr_ram(&rom);
+        Mmc3 {
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+            chr: chr,
+            is_chr_ram: is_chr_ram,
+            bank_select: 0,
+            bank_registers: [0; 8],
+            mirroring: rom.screen_mirroring,
+            prg_ram_enabled: true,
+            prg_ram_write_protected: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_low_fetches: 0,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, data: u8) {
+        let is_even = address & 1 == 0;
+        match (address, is_even) {
+            (0x8000..=0x9FFF, true) => self.bank_select = data,
+            (0x8000..=0x9FFF, false) => {
+                self.bank_registers[(self.bank_select & 0b111) as usize] = data
+            }
+            (0xA000..=0xBFFF, true) => {
+                self.mirroring = match data & 1 {
+                    0 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                }
+            }
+            (0xA000..=0xBFFF, false) => {
+                self.prg_ram_enabled = data & 0b1000_0000 != 0;
+                self.prg_ram_write_protected = data & 0b0100_0000 != 0;
+            }
+            (0xC000..=0xDFFF, true) => self.irq_latch = data,
+            (0xC000..=0xDFFF, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xE000..=0xFFFF, true) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xE000..=0xFFFF, false) => self.irq_enabled = true,
+            _ => panic!("Impossible!"),
+        }
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    fn prg_rom_address(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        // a single bank ROM has no second last bank, the fixed windows all show the same one
+        let second_last_bank = bank_count.saturating_sub(2);
+        let is_prg_mode_swapped = self.bank_select & 0b0100_0000 != 0;
+
+        let bank = match (address, is_prg_mode_swapped) {
+            (0x8000..=0x9FFF, false) => self.bank_registers[6] as usize,
+            (0x8000..=0x9FFF, true) => second_last_bank,
+            (0xA000..=0xBFFF, _) => self.bank_registers[7] as usize,
+            (0xC000..=0xDFFF, false) => second_last_bank,
+            (0xC000..=0xDFFF, true) => self.bank_registers[6] as usize,
+            (0xE000..=0xFFFF, _) => bank_count - 1,
+            _ => panic!("Address {:0x} is not in PRG ROM", address),
+        };
+
+        let offset = address as usize % PRG_BANK_SIZE;
+        return (bank % bank_count) * PRG_BANK_SIZE + offset;
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        // with A12 inversion the 2KB banks are mapped to $1000-$1FFF instead
+        let address = match self.bank_select & 0b1000_0000 != 0 {
+            true => address ^ 0x1000,
+            false => address,
+        };
+
+        let bank = match address {
+            0x0000..=0x07FF => {
+                (self.bank_registers[0] & 0b1111_1110) as usize | (address as usize >> 10 & 1)
+            }
+            0x0800..=0x0FFF => {
+                (self.bank_registers[1] & 0b1111_1110) as usize | (address as usize >> 10 & 1)
+            }
+            0x1000..=0x13FF => self.bank_registers[2] as usize,
+            0x1400..=0x17FF => self.bank_registers[3] as usize,
+            0x1800..=0x1BFF => self.bank_registers[4] as usize,
+            0x1C00..=0x1FFF => self.bank_registers[5] as usize,
+            _ => panic!("Address {:0x} is not in CHR", address),
+        };
+
+        let offset = address as usize % CHR_BANK_SIZE;
+        return (bank * CHR_BANK_SIZE + offset) % self.chr.len();
+    }
+}
+
+impl Cartridge for Mmc3 {
+    fn cpu_read(&self, address: u16) -> u8 {
+        match address {
+            PRG_RAM_START..=PRG_RAM_END => {
+                if self.prg_ram_enabled {
+                    return self.prg_ram[(address - PRG_RAM_START) as usize];
+                }
+                return 0;
+            }
+            PRG_ROM_START..=0xFFFF => self.prg_rom[self.prg_rom_address(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            PRG_RAM_START..=PRG_RAM_END
+                if self.prg_ram_enabled && !self.prg_ram_write_protected =>
+            {
+                self.prg_ram[(address - PRG_RAM_START) as usize] = data;
+            }
+            PRG_ROM_START..=0xFFFF => self.write_register(address, data),
+            _ => (),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> u8 {
+        return self.chr[self.chr_address(address)];
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.is_chr_ram {
+            let chr_address = self.chr_address(address);
+            self.chr[chr_address] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        return self.mirroring.clone();
+    }
+
+    fn irq(&self) -> bool {
+        return self.irq_pending;
+    }
+
+    fn notify_ppu_fetch(&mut self, address: u16) {
+        // The counter is clocked on rising edges of PPU A12. 8x16 sprites can fetch from
+        // both pattern tables, the filter keeps those short toggles from clocking it again.
+        if address & 0x1000 == 0 {
+            self.a12_low_fetches = self.a12_low_fetches.saturating_add(1);
+            return;
+        }
+        if self.a12_low_fetches >= A12_LOW_FETCHES {
+            self.clock_irq_counter();
+        }
+        self.a12_low_fetches = 0;
+    }
+}
+
+#[cfg(test)]
+mod test_mmc3 {
+    use super::*;
+    use test_case::test_case;
+
+    // every 8KB PRG bank and 1KB CHR bank is filled with its own number
+    fn test_mmc3() -> Mmc3 {
+        let prg_rom = (0..16)
+            .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+            .collect();
+        let chr_rom = (0..32)
+            .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+            .collect();
+        return Mmc3::new(Rom::new(prg_rom, chr_rom, 4, Mirroring::Vertical));
+    }
+
+    fn write_bank_register(mmc3: &mut Mmc3, bank_select: u8, data: u8) {
+        mmc3.cpu_write(0x8000, bank_select);
+        mmc3.cpu_write(0x8001, data);
+    }
+
+    // one scanline worth of fetches, background from $0000 and sprites from $1000
+    fn render_scanline(mmc3: &mut Mmc3) {
+        for _ in 0..32 {
+            mmc3.notify_ppu_fetch(0x0000);
+        }
+        for _ in 0..8 {
+            mmc3.notify_ppu_fetch(0x1000);
+        }
+        for _ in 0..2 {
+            mmc3.notify_ppu_fetch(0x0000);
+        }
+    }
+
+    // 8x16 sprites fetch from the pattern table picked by each tile index
+    fn render_mixed_sprite_scanline(mmc3: &mut Mmc3) {
+        for _ in 0..32 {
+            mmc3.notify_ppu_fetch(0x0000);
+        }
+        for address in [
+            0x1000, 0x0000, 0x1000, 0x0000, 0x0000, 0x1000, 0x0000, 0x1000,
+        ] {
+            mmc3.notify_ppu_fetch(address);
+        }
+        for _ in 0..2 {
+            mmc3.notify_ppu_fetch(0x0000);
+        }
+    }
+
+    #[test_case(0b0000_0000, [3, 5, 14, 15])]
+    #[test_case(0b0100_0000, [14, 5, 3, 15])]
+    fn test_prg_bank_modes(bank_select: u8, expected: [u8; 4]) {
+        let mut mmc3 = test_mmc3();
+        write_bank_register(&mut mmc3, 6, 3);
+        write_bank_register(&mut mmc3, 7, 5);
+        mmc3.cpu_write(0x8000, bank_select | 6);
+        assert_eq!(expected[0], mmc3.cpu_read(0x8000));
+        assert_eq!(expected[1], mmc3.cpu_read(0xA000));
+        assert_eq!(expected[2], mmc3.cpu_read(0xC000));
+        assert_eq!(expected[3], mmc3.cpu_read(0xE000));
+    }
+
+    #[test]
+    fn test_single_prg_bank() {
+        let mut mmc3 = Mmc3::new(Rom::new(
+            vec![7; PRG_BANK_SIZE],
+            vec![],
+            4,
+            Mirroring::Vertical,
+        ));
+        for address in [0x8000, 0xA000, 0xC000, 0xE000] {
+            assert_eq!(7, mmc3.cpu_read(address));
+        }
+        mmc3.cpu_write(0x8000, 0b0100_0110);
+        assert_eq!(7, mmc3.cpu_read(0x8000));
+    }
+
+    #[test_case(0b0000_0000, [2, 3, 8, 9, 20, 21])]
+    #[test_case(0b1000_0000, [20, 21, 0, 0, 2, 3])]
+    fn test_chr_bank_modes(bank_select: u8, expected: [u8; 6]) {
+        let mut mmc3 = test_mmc3();
+        write_bank_register(&mut mmc3, bank_select, 3);
+        write_bank_register(&mut mmc3, bank_select | 1, 8);
+        write_bank_register(&mut mmc3, bank_select | 2, 20);
+        write_bank_register(&mut mmc3, bank_select | 3, 21);
+        assert_eq!(expected[0], mmc3.ppu_read(0x0000));
+        assert_eq!(expected[1], mmc3.ppu_read(0x0400));
+        assert_eq!(expected[2], mmc3.ppu_read(0x0800));
+        assert_eq!(expected[3], mmc3.ppu_read(0x0C00));
+        assert_eq!(expected[4], mmc3.ppu_read(0x1000));
+        assert_eq!(expected[5], mmc3.ppu_read(0x1400));
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut mmc3 = test_mmc3();
+        mmc3.cpu_write(0xA000, 1);
+        assert_eq!(Mirroring::Horizontal, mmc3.mirroring());
+        mmc3.cpu_write(0xA000, 0);
+        assert_eq!(Mirroring::Vertical, mmc3.mirroring());
+    }
+
+    #[test]
+    fn test_prg_ram_protect() {
+        let mut mmc3 = test_mmc3();
+        mmc3.cpu_write(0xA001, 0b1000_0000);
+        mmc3.cpu_write(0x6000, 0xAA);
+        assert_eq!(0xAA, mmc3.cpu_read(0x6000));
+
+        mmc3.cpu_write(0xA001, 0b1100_0000);
+        mmc3.cpu_write(0x6000, 0xBB);
+        assert_eq!(0xAA, mmc3.cpu_read(0x6000));
+
+        mmc3.cpu_write(0xA001, 0b0000_0000);
+        assert_eq!(0x00, mmc3.cpu_read(0x6000));
+    }
+
+    #[test]
+    fn test_scanline_irq() {
+        let mut mmc3 = test_mmc3();
+        mmc3.cpu_write(0xC000, 3);
+        mmc3.cpu_write(0xC001, 0);
+        mmc3.cpu_write(0xE001, 0);
+
+        // the first clock reloads the counter
+        for _ in 0..3 {
+            render_scanline(&mut mmc3);
+            assert!(!mmc3.irq());
+        }
+        render_scanline(&mut mmc3);
+        assert!(mmc3.irq());
+
+        mmc3.cpu_write(0xE000, 0);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn test_mixed_sprite_pattern_tables_clock_once() {
+        let mut mmc3 = test_mmc3();
+        mmc3.cpu_write(0xC000, 2);
+        mmc3.cpu_write(0xC001, 0);
+        mmc3.cpu_write(0xE001, 0);
+
+        // one clock per scanline, the first one reloads the counter
+        for _ in 0..2 {
+            render_mixed_sprite_scanline(&mut mmc3);
+            assert!(!mmc3.irq());
+        }
+        render_mixed_sprite_scanline(&mut mmc3);
+        assert!(mmc3.irq());
+    }
+
+    #[test]
+    fn test_scanline_irq_disabled() {
+        let mut mmc3 = test_mmc3();
+        mmc3.cpu_write(0xC000, 1);
+        mmc3.cpu_write(0xC001, 0);
+        for _ in 0..4 {
+            render_scanline(&mut mmc3);
+        }
+        assert!(!mmc3.irq());
+    }
+}
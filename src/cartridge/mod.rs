@@ -0,0 +1,117 @@
+pub mod four_screen;
+pub mod mmc1;
+pub mod mmc3;
+pub mod nrom;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::cartridge::four_screen::FourScreen;
+use crate::cartridge::mmc1::Mmc1;
+use crate::cartridge::mmc3::Mmc3;
+use crate::cartridge::nrom::Nrom;
+use crate::rom::*;
+
+pub const SUPPORTED_MAPPERS: [u8; 3] = [0, 1, 4];
+
+// The board plugged into the console. The CPU sees it at $4020-$FFFF
+// and the PPU reads its pattern tables from it at $0000-$1FFF.
+// Nametable accesses at $2000-$3EFF also go through the board, which decides
+// how they map onto the 2KB of VRAM inside the console.
+pub trait Cartridge {
+    fn cpu_read(&self, address: u16) -> u8;
+
+    fn cpu_write(&mut self, address: u16, data: u8);
+
+    fn ppu_read(&self, address: u16) -> u8;
+
+    fn ppu_write(&mut self, address: u16, data: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    fn nametable_read(&self, address: u16, vram: &[u8; 2048]) -> u8 {
+        return vram[self.vram_address(address)];
+    }
+
+    fn nametable_write(&mut self, address: u16, data: u8, vram: &mut [u8; 2048]) {
+        vram[self.vram_address(address)] = data;
+    }
+
+    // https://www.nesdev.org/wiki/Mirroring#Nametable_Mirroring
+    fn vram_address(&self, address: u16) -> usize {
+        let nametable = (address >> 10) & 0b11;
+        let offset = address & 0x03FF;
+        let page = match self.mirroring() {
+            // four screen boards bring their own VRAM, see FourScreen
+            Mirroring::Vertical | Mirroring::FourScreen => nametable & 1,
+            Mirroring::Horizontal => nametable >> 1,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+        };
+        return (page * 0x400 + offset) as usize;
+    }
+
+    fn irq(&self) -> bool {
+        return false;
+    }
+
+    // Called with the address of every pattern table fetch the PPU makes while rendering,
+    // for boards that watch the PPU address bus (e.g. the MMC3 scanline counter)
+    fn notify_ppu_fetch(&mut self, _address: u16) {}
+}
+
+pub fn new(rom: Rom) -> Rc<RefCell<dyn Cartridge>> {
+    let is_four_screen = rom.screen_mirroring == Mirroring::FourScreen;
+    match rom.mapper {
+        0 => with_vram(Nrom::new(rom), is_four_screen),
+        1 => with_vram(Mmc1::new(rom), is_four_screen),
+        4 => with_vram(Mmc3::new(rom), is_four_screen),
+        _ => panic!("Mapper {} is not implemented!", rom.mapper),
+    }
+}
+
+fn with_vram<C: Cartridge + 'static>(board: C, is_four_screen: bool) -> Rc<RefCell<dyn Cartridge>> {
+    if is_four_screen {
+        return Rc::new(RefCell::new(FourScreen::new(board)));
+    }
+    return Rc::new(RefCell::new(board));
+}
+
+// Boards without CHR ROM come with 8KB of CHR RAM instead
+fn chr_or_chr_ram(rom: &Rom) -> (Vec<u8>, bool) {
+    if rom.chr_rom.is_empty() {
+        return (vec![0; 0x2000], true);
+    }
+    return (rom.chr_rom.clone(), false);
+}
+
+#[cfg(test)]
+mod test_cartridge {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(Mirroring::Vertical, [0x000, 0x400, 0x000, 0x400])]
+    #[test_case(Mirroring::Horizontal, [0x000, 0x000, 0x400, 0x400])]
+    #[test_case(Mirroring::SingleScreenLower, [0x000, 0x000, 0x000, 0x000])]
+    #[test_case(Mirroring::SingleScreenUpper, [0x400, 0x400, 0x400, 0x400])]
+    fn test_vram_address(mirroring: Mirroring, expected: [usize; 4]) {
+        let nrom = Nrom::new(Rom::new(vec![0; 0x4000], vec![], 0, mirroring));
+        assert_eq!(expected[0] + 0x12, nrom.vram_address(0x2012));
+        assert_eq!(expected[1] + 0x12, nrom.vram_address(0x2412));
+        assert_eq!(expected[2] + 0x12, nrom.vram_address(0x2812));
+        assert_eq!(expected[3] + 0x12, nrom.vram_address(0x2C12));
+        // $3000-$3EFF mirrors $2000-$2EFF
+        assert_eq!(expected[0] + 0x12, nrom.vram_address(0x3012));
+    }
+
+    #[test]
+    fn test_four_screen_rom_gets_its_own_vram() {
+        let cartridge = new(Rom::new(vec![0; 0x4000], vec![], 0, Mirroring::FourScreen));
+        let mut vram = [0; 2048];
+        cartridge
+            .borrow_mut()
+            .nametable_write(0x2C00, 0xAA, &mut vram);
+        assert_eq!(0xAA, cartridge.borrow().nametable_read(0x2C00, &vram));
+        assert_eq!(0x00, cartridge.borrow().nametable_read(0x2400, &vram));
+    }
+}
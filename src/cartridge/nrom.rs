@@ -0,0 +1,90 @@
+use crate::cartridge::*;
+
+const PRG_ROM_START: u16 = 0x8000;
+
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    is_chr_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(rom: Rom) -> Self {
+        let (chr, is_chr_ram) = chr_or_chr_ram(&rom);
+        Nrom {
+            prg_rom: rom.prg_rom,
+            chr: chr,
+            is_chr_ram: is_chr_ram,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+
+    fn calculate_rom_address(&self, mut address: u16) -> u16 {
+        address -= PRG_ROM_START;
+        if self.prg_rom.len() == 0x4000 && address >= 0x4000 {
+            //mirror if needed
+            address = address % 0x4000;
+        }
+        return address;
+    }
+}
+
+impl Cartridge for Nrom {
+    fn cpu_read(&self, address: u16) -> u8 {
+        match address {
+            PRG_ROM_START..=0xFFFF => self.prg_rom[self.calculate_rom_address(address) as usize],
+            _ => 0,
+        }
+    }
+
+    // PRG ROM ignores writes
+    fn cpu_write(&mut self, _address: u16, _data: u8) {}
+
+    fn ppu_read(&self, address: u16) -> u8 {
+        return self.chr[address as usize];
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) {
+        if self.is_chr_ram {
+            self.chr[address as usize] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        return self.mirroring.clone();
+    }
+}
+
+#[cfg(test)]
+mod test_nrom {
+    use super::*;
+
+    #[test]
+    fn test_16kb_prg_rom_is_mirrored() {
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[0x0010] = 0xAA;
+        let nrom = Nrom::new(Rom::new(prg_rom, vec![0; 0x2000], 0, Mirroring::Vertical));
+        assert_eq!(0xAA, nrom.cpu_read(0x8010));
+        assert_eq!(0xAA, nrom.cpu_read(0xC010));
+    }
+
+    #[test]
+    fn test_chr_rom_is_read_only() {
+        let mut nrom = Nrom::new(Rom::new(
+            vec![0; 0x4000],
+            vec![0; 0x2000],
+            0,
+            Mirroring::Vertical,
+        ));
+        nrom.ppu_write(0x0010, 0xAA);
+        assert_eq!(0x00, nrom.ppu_read(0x0010));
+    }
+
+    #[test]
+    fn test_chr_ram_is_writable() {
+        let mut nrom = Nrom::new(Rom::new(vec![0; 0x4000], vec![], 0, Mirroring::Vertical));
+        nrom.ppu_write(0x1FFF, 0xAA);
+        assert_eq!(0xAA, nrom.ppu_read(0x1FFF));
+    }
+}
@@ -9,11 +9,11 @@ pub struct Instruction {
     pub name: &'static str,
     pub bytes: u8,
     pub addressing_mode: AddressingModes,
-    pub cycles: u8,
+    pub cycles: u16,
 }
 
 pub struct InstructionResult {
-    pub executed_cycles: u8,
+    pub executed_cycles: u16,
 }
 
 impl Instruction {
@@ -335,7 +335,7 @@ fn adc(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
 
     cpu.update_negative_flag(cpu.register_a);
     cpu.update_zero_flag(cpu.register_a);
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     return instruction_result;
 }
 
@@ -347,7 +347,7 @@ fn and(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
     cpu.register_a = cpu.register_a & operand;
     cpu.update_zero_flag(cpu.register_a);
     cpu.update_negative_flag(cpu.register_a);
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     return instruction_result;
 }
 
@@ -390,7 +390,7 @@ fn bcc(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
             let distance = cpu.mapper.borrow().read_u8(cpu.program_counter);
             let page_crossed = cpu.branch_off_program_counter(distance);
             instruction_result.executed_cycles += 1;
-            instruction_result.executed_cycles += page_crossed as u8;
+            instruction_result.executed_cycles += page_crossed as u16;
         }
         FlagStates::SET => (),
     }
@@ -406,7 +406,7 @@ fn bcs(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
             let distance = cpu.mapper.borrow().read_u8(cpu.program_counter);
             let page_crossed = cpu.branch_off_program_counter(distance);
             instruction_result.executed_cycles += 1;
-            instruction_result.executed_cycles += page_crossed as u8;
+            instruction_result.executed_cycles += page_crossed as u16;
         }
         FlagStates::CLEAR => (),
     }
@@ -422,7 +422,7 @@ fn beq(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
             let distance = cpu.mapper.borrow().read_u8(cpu.program_counter);
             let page_crossed = cpu.branch_off_program_counter(distance);
             instruction_result.executed_cycles += 1;
-            instruction_result.executed_cycles += page_crossed as u8;
+            instruction_result.executed_cycles += page_crossed as u16;
         }
         FlagStates::CLEAR => (),
     }
@@ -456,7 +456,7 @@ fn bmi(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
             let distance = cpu.mapper.borrow().read_u8(cpu.program_counter);
             let page_crossed = cpu.branch_off_program_counter(distance);
             instruction_result.executed_cycles += 1;
-            instruction_result.executed_cycles += page_crossed as u8;
+            instruction_result.executed_cycles += page_crossed as u16;
         }
         FlagStates::CLEAR => (),
     }
@@ -472,7 +472,7 @@ fn bne(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
             let distance = cpu.mapper.borrow().read_u8(cpu.program_counter);
             let page_crossed = cpu.branch_off_program_counter(distance);
             instruction_result.executed_cycles += 1;
-            instruction_result.executed_cycles += page_crossed as u8;
+            instruction_result.executed_cycles += page_crossed as u16;
         }
         FlagStates::SET => (),
     }
@@ -488,7 +488,7 @@ fn bpl(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
             let distance = cpu.mapper.borrow().read_u8(cpu.program_counter);
             let page_crossed = cpu.branch_off_program_counter(distance);
             instruction_result.executed_cycles += 1;
-            instruction_result.executed_cycles += page_crossed as u8;
+            instruction_result.executed_cycles += page_crossed as u16;
         }
         FlagStates::SET => (),
     }
@@ -504,7 +504,7 @@ fn bvc(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
             let distance = cpu.mapper.borrow().read_u8(cpu.program_counter);
             let page_crossed = cpu.branch_off_program_counter(distance);
             instruction_result.executed_cycles += 1;
-            instruction_result.executed_cycles += page_crossed as u8;
+            instruction_result.executed_cycles += page_crossed as u16;
         }
         FlagStates::SET => (),
     }
@@ -520,7 +520,7 @@ fn bvs(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
             let distance = cpu.mapper.borrow().read_u8(cpu.program_counter);
             let page_crossed = cpu.branch_off_program_counter(distance);
             instruction_result.executed_cycles += 1;
-            instruction_result.executed_cycles += page_crossed as u8;
+            instruction_result.executed_cycles += page_crossed as u16;
         }
         FlagStates::CLEAR => (),
     }
@@ -528,7 +528,7 @@ fn bvs(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
 }
 
 fn brk(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
-    let interrupt_vector = cpu.mapper.borrow().read_u16(0xFFFE);
+    let interrupt_vector = cpu.mapper.borrow().read_u16(IRQ_VECTOR);
     cpu.stack_push_u16(cpu.program_counter.wrapping_add(1));
     cpu.stack_push(cpu.status | 0b0001_0000);
     cpu.set_flag(STATUS_FLAG_INTERRUPT_DISABLE);
@@ -581,7 +581,7 @@ fn cmp(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
 
     cpu.update_zero_flag(result);
     cpu.update_negative_flag(result);
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     return instruction_result;
 }
 
@@ -665,7 +665,7 @@ fn eor(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
     cpu.register_a = cpu.register_a ^ operand;
     cpu.update_zero_flag(cpu.register_a);
     cpu.update_negative_flag(cpu.register_a);
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     return instruction_result;
 }
 
@@ -678,7 +678,7 @@ fn lda(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
     cpu.register_a = operand;
     cpu.update_zero_flag(cpu.register_a);
     cpu.update_negative_flag(cpu.register_a);
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     return instruction_result;
 }
 
@@ -690,7 +690,7 @@ fn ldx(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
     cpu.register_x = operand;
     cpu.update_zero_flag(cpu.register_x);
     cpu.update_negative_flag(cpu.register_x);
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     return instruction_result;
 }
 
@@ -702,7 +702,7 @@ fn ldy(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
     cpu.register_y = operand;
     cpu.update_zero_flag(cpu.register_y);
     cpu.update_negative_flag(cpu.register_y);
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     return instruction_result;
 }
 
@@ -742,7 +742,7 @@ fn ora(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
     cpu.register_a = cpu.register_a | operand;
     cpu.update_zero_flag(cpu.register_a);
     cpu.update_negative_flag(cpu.register_a);
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     return instruction_result;
 }
 
@@ -986,7 +986,7 @@ fn sbc(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
 
     cpu.update_negative_flag(cpu.register_a);
     cpu.update_zero_flag(cpu.register_a);
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     return instruction_result;
 }
 
@@ -1087,7 +1087,7 @@ fn top(instruction: &Instruction, cpu: &mut CPU) -> InstructionResult {
         executed_cycles: instruction.cycles,
     };
 
-    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u8;
+    instruction_result.executed_cycles += instruction.addressing_mode.is_page_crossed(cpu) as u16;
     nop(instruction, cpu);
     nop(instruction, cpu);
     nop(instruction, cpu);
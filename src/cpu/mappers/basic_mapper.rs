@@ -2,41 +2,46 @@ use core::panic;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::apu::APU;
+use crate::cartridge::Cartridge;
 use crate::controller::*;
 use crate::memory::*;
 use crate::ppu::PPU;
-use crate::rom::*;
 
 const RAM_START: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS_START: u16 = 0x2000;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
-const ROM_START: u16 = 0x8000;
-const ROM_END: u16 = 0xFFFF;
+const CARTRIDGE_START: u16 = 0x4020;
+const CARTRIDGE_END: u16 = 0xFFFF;
 pub struct BasicMapper {
     ram: [u8; 2048],
-    rom: Rom,
+    cartridge: Rc<RefCell<dyn Cartridge>>,
     ppu: Rc<RefCell<PPU>>,
+    apu: Rc<RefCell<APU>>,
     controller: Rc<RefCell<Controller>>,
+    dma_occured: bool,
 }
 
 impl BasicMapper {
-    pub fn new(rom: Rom, ppu: Rc<RefCell<PPU>>, controller: Rc<RefCell<Controller>>) -> Self {
+    pub fn new(
+        cartridge: Rc<RefCell<dyn Cartridge>>,
+        ppu: Rc<RefCell<PPU>>,
+        apu: Rc<RefCell<APU>>,
+        controller: Rc<RefCell<Controller>>,
+    ) -> Self {
         BasicMapper {
             ram: [0; 2048],
-            rom: rom,
+            cartridge: cartridge,
             ppu: ppu,
+            apu: apu,
             controller: controller,
+            dma_occured: false,
         }
     }
 
-    fn calculate_rom_address(&self, mut address: u16) -> u16 {
-        address -= ROM_START;
-        if self.rom.prg_rom.len() == 0x4000 && address >= 0x4000 {
-            //mirror if needed
-            address = address % 0x4000;
-        }
-        return address;
+    pub fn ram(&self) -> &[u8; 2048] {
+        return &self.ram;
     }
 }
 
@@ -49,6 +54,16 @@ impl Memory for BasicMapper {
         return nmi_occured;
     }
 
+    fn dma_occured(&mut self) -> bool {
+        let dma_occured = self.dma_occured;
+        self.dma_occured = false;
+        return dma_occured;
+    }
+
+    fn irq_asserted(&self) -> bool {
+        return self.cartridge.borrow().irq() || self.apu.borrow().irq();
+    }
+
     fn read_u8(&self, address: u16) -> u8 {
         match address {
             RAM_START..=RAM_MIRRORS_END => {
@@ -57,28 +72,25 @@ impl Memory for BasicMapper {
             }
             PPU_REGISTERS_START..=PPU_REGISTERS_MIRRORS_END => {
                 let mirror_down_address = address & 0b00100000_00000111;
+                let mut ppu = self.ppu.borrow_mut();
                 match mirror_down_address {
-                    0x2000 => panic!("Control register is write-only!"),
-                    0x2001 => panic!("Mask register is write-only!"),
-                    0x2002 => self.ppu.borrow_mut().read_status(),
-                    0x2003 => panic!("OAMADDR register is write only!"),
-                    0x2004 => panic!("OAMDATA register is not implemented yet!"),
-                    0x2005 => panic!("Scroll register is write-only!"),
-                    0x2006 => panic!("Address register is write-only!"),
-                    0x2007 => self.ppu.borrow_mut().read_data(),
+                    // write-only registers
+                    0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => ppu.read_io_latch(),
+                    0x2002 => ppu.read_status(),
+                    0x2004 => ppu.read_oam_data(),
+                    0x2007 => ppu.read_data(),
                     _ => panic!("Impossible"),
                 }
             }
-            0x4000..=0x4015 | 0x4017 => {
-                println!(
-                    "Ignoring read from {:0x}, APU and IO are not implemented yet, returning 0",
-                    address
-                );
-                return 0;
-            }
+            // write-only APU registers
+            0x4000..=0x4014 => 0,
+            0x4015 => self.apu.borrow_mut().read_status(),
+            // the second controller is not implemented yet
+            0x4017 => 0,
             0x4016 => self.controller.borrow_mut().read_u8(),
-            ROM_START..=ROM_END => self.rom.prg_rom[self.calculate_rom_address(address) as usize],
-            _ => panic!("Can't read address {:0x}", address),
+            // APU and I/O test registers, disabled on retail consoles
+            0x4018..=0x401F => 0,
+            CARTRIDGE_START..=CARTRIDGE_END => self.cartridge.borrow().cpu_read(address),
         }
     }
 
@@ -90,39 +102,36 @@ impl Memory for BasicMapper {
             }
             PPU_REGISTERS_START..=PPU_REGISTERS_MIRRORS_END => {
                 let mirror_down_address = address & 0b00100000_00000111;
+                let mut ppu = self.ppu.borrow_mut();
+                ppu.write_io_latch(data);
                 match mirror_down_address {
-                    0x2000 => self.ppu.borrow_mut().write_control(data),
-                    0x2001 => self.ppu.borrow_mut().write_mask(data),
-                    0x2002 => panic!("Status register is read-only!"),
-                    0x2003 => self.ppu.borrow_mut().write_oam_address(data),
-                    0x2004 => println!("OAMDATA register is not implemented yet!"),
-                    0x2005 => println!("Scroll register is not implemented yet!"),
-                    0x2006 => self.ppu.borrow_mut().write_address(data),
-                    0x2007 => self.ppu.borrow_mut().write_data(data),
+                    0x2000 => ppu.write_control(data),
+                    0x2001 => ppu.write_mask(data),
+                    // status is read-only, the write only reaches the latch
+                    0x2002 => (),
+                    0x2003 => ppu.write_oam_address(data),
+                    0x2004 => ppu.write_oam_data(data),
+                    0x2005 => ppu.write_scroll(data),
+                    0x2006 => ppu.write_address(data),
+                    0x2007 => ppu.write_data(data),
                     _ => panic!("Impossible!"),
                 }
             }
             0x4000..=0x4013 | 0x4015 | 0x4017 => {
-                println!(
-                    "Ignoring write to {:0x}, APU and IO are not implemented yet",
-                    address
-                );
+                self.apu.borrow_mut().write_register(address, data)
             }
             0x4014 => {
-                let address = u16::from_be_bytes([data, 0x00]) as usize;
-                // possible timing issue. Normally, this would take 513-514 cycles,
-                // but here it will only require a few (depends on the executed instruction)
-                self.ppu
-                    .borrow_mut()
-                    .dma_write(&self.ram[address..address + 256]);
+                let start = u16::from_be_bytes([data, 0x00]);
+                let page: Vec<u8> = (0..256).map(|i| self.read_u8(start + i)).collect();
+                self.ppu.borrow_mut().dma_write(&page);
+                self.dma_occured = true;
             }
             0x4016 => {
                 self.controller.borrow_mut().write(data);
             }
-            _ => println!(
-                "Attempt to write read-only memory at address {:0x}",
-                address
-            ),
+            CARTRIDGE_START..=CARTRIDGE_END => self.cartridge.borrow_mut().cpu_write(address, data),
+            // unmapped addresses ignore writes
+            _ => (),
         }
     }
 
@@ -140,14 +149,11 @@ impl Memory for BasicMapper {
             PPU_REGISTERS_START..=PPU_REGISTERS_MIRRORS_END => {
                 panic!("Can't read 2 bytes from PPU registers")
             }
-            ROM_START..=ROM_END => {
-                let rom_address = self.calculate_rom_address(address);
-                // duplicate code
-                let low_order_address = rom_address;
-                let high_order_address = rom_address.wrapping_add(1);
+            CARTRIDGE_START..=CARTRIDGE_END => {
+                let cartridge = self.cartridge.borrow();
                 return u16::from_le_bytes([
-                    self.rom.prg_rom[low_order_address as usize],
-                    self.rom.prg_rom[high_order_address as usize],
+                    cartridge.cpu_read(address),
+                    cartridge.cpu_read(address.wrapping_add(1)),
                 ]);
             }
             _ => panic!("Can't read address {:0x}", address),
@@ -1,17 +1,32 @@
 use crate::memory::*;
+use std::cell::Cell;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TestMapper {
     memory: [u8; 65536],
+    pub nmi: Cell<bool>,
+    pub irq: bool,
 }
 
 impl TestMapper {
     pub fn new() -> Self {
-        TestMapper { memory: [0; 65536] }
+        TestMapper {
+            memory: [0; 65536],
+            nmi: Cell::new(false),
+            irq: false,
+        }
     }
 }
 
 impl Memory for TestMapper {
+    fn nmi_occured(&self) -> bool {
+        return self.nmi.replace(false);
+    }
+
+    fn irq_asserted(&self) -> bool {
+        return self.irq;
+    }
+
     fn read_u8(&self, address: u16) -> u8 {
         return self.memory[address as usize];
     }
@@ -5,6 +5,7 @@ pub mod mappers;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+pub use crate::cpu::instructions::InstructionResult;
 use crate::cpu::instructions::*;
 use crate::memory::Memory;
 
@@ -16,6 +17,13 @@ pub const STATUS_FLAG_INTERRUPT_DISABLE: u8 = 0b0000_0100;
 pub const STATUS_FLAG_ZERO: u8 = 0b00000010;
 pub const STATUS_FLAG_CARRY: u8 = 0b00000001;
 
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+const INTERRUPT_CYCLES: u16 = 7;
+// https://www.nesdev.org/wiki/PPU_registers#OAMDMA, one more when the DMA starts on an odd cycle
+const OAM_DMA_CYCLES: u16 = 513;
+
 #[derive(Debug, PartialEq)]
 enum FlagStates {
     CLEAR = 0,
@@ -31,6 +39,13 @@ pub struct CPU {
     program_counter: u16,
     stack_pointer: u8,
     mapper: Rc<RefCell<dyn Memory>>,
+    // total number of executed cycles, the DMA alignment depends on it
+    cycles: u64,
+    // The interrupt disable flag as seen by the IRQ poll at the end of the last instruction.
+    // CLI, SEI and PLP change the flag after the poll, so their effect is delayed by one instruction.
+    irq_inhibited: bool,
+    // BRK has pushed its return address and status, but an NMI arriving now still takes its vector
+    is_brk_hijackable: bool,
 }
 
 impl CPU {
@@ -43,14 +58,19 @@ impl CPU {
             program_counter: 0x8000,
             stack_pointer: 0xFF,
             mapper,
+            cycles: 0,
+            irq_inhibited: false,
+            is_brk_hijackable: false,
         }
     }
 
     pub fn reset(&mut self) {
         self.register_a = 0;
         self.register_x = 0;
-        self.status = 0;
-        self.program_counter = self.mapper.borrow().read_u16(0xFFFC);
+        self.status = STATUS_FLAG_INTERRUPT_DISABLE;
+        self.irq_inhibited = true;
+        self.is_brk_hijackable = false;
+        self.program_counter = self.mapper.borrow().read_u16(RESET_VECTOR);
     }
 
     pub fn run(&mut self) {
@@ -71,7 +91,7 @@ impl CPU {
 
     pub fn execute_next_instruction(&mut self) -> InstructionResult {
         let mut instruction_result = InstructionResult { executed_cycles: 0 };
-        instruction_result.executed_cycles += self.handle_nmi_interrupt();
+        instruction_result.executed_cycles += self.handle_interrupts();
         // println!("PC: {:0x}", self.program_counter);
 
         let opcode = self.fetch();
@@ -81,29 +101,63 @@ impl CPU {
         match decoded_opcode {
             None => panic!("Could not decode opcode 0x{:02x}", opcode),
             Some(instruction) => {
-                instruction_result.executed_cycles = instruction.execute(self).executed_cycles;
+                let interrupt_disable_before = self.status & STATUS_FLAG_INTERRUPT_DISABLE != 0;
+                instruction_result.executed_cycles += instruction.execute(self).executed_cycles;
+                self.irq_inhibited = match instruction.name {
+                    "CLI" | "SEI" | "PLP" => interrupt_disable_before,
+                    _ => self.status & STATUS_FLAG_INTERRUPT_DISABLE != 0,
+                };
+                self.is_brk_hijackable = instruction.name == "BRK";
                 self.update_program_counter(instruction);
 
+                if self.mapper.borrow_mut().dma_occured() {
+                    let is_odd_cycle =
+                        (self.cycles + instruction_result.executed_cycles as u64) % 2 == 1;
+                    instruction_result.executed_cycles += OAM_DMA_CYCLES + is_odd_cycle as u16;
+                }
+                self.cycles += instruction_result.executed_cycles as u64;
+
                 return instruction_result;
             }
         }
     }
 
+    // Kept for callers that step the CPU themselves, pending IRQs are serviced as well
     pub fn handle_nmi_interrupt(&mut self) -> u8 {
-        if self.mapper.borrow().nmi_occured() {
-            let interrupt_vector = self.mapper.borrow().read_u16(0xFFFA);
-            self.stack_push_u16(self.program_counter);
-
-            self.stack_push(self.status | 0b0001_0000);
-            self.set_flag(STATUS_FLAG_INTERRUPT_DISABLE);
+        return self.handle_interrupts() as u8;
+    }
 
-            self.program_counter = interrupt_vector;
-            // println!("NMI occurred, setting PC to: {:0x}", self.program_counter);
-            // todo: check if this is the correct number of cycles
-            return 2;
-        } else {
+    fn handle_interrupts(&mut self) -> u16 {
+        let nmi_occured = self.mapper.borrow().nmi_occured();
+        if nmi_occured && self.is_brk_hijackable {
+            // The instructions are not executed cycle by cycle, so an NMI that
+            // arrived at any point during BRK is treated as if it came before the vector fetch.
+            // The stack already holds the BRK return address and status, only the vector changes.
+            self.is_brk_hijackable = false;
+            self.program_counter = self.mapper.borrow().read_u16(NMI_VECTOR);
             return 0;
         }
+        self.is_brk_hijackable = false;
+
+        if nmi_occured {
+            return self.interrupt(NMI_VECTOR);
+        }
+        if !self.irq_inhibited && self.mapper.borrow().irq_asserted() {
+            return self.interrupt(IRQ_VECTOR);
+        }
+        return 0;
+    }
+
+    fn interrupt(&mut self, vector: u16) -> u16 {
+        let interrupt_vector = self.mapper.borrow().read_u16(vector);
+        self.stack_push_u16(self.program_counter);
+
+        // hardware interrupts push the status with the break flag cleared
+        self.stack_push((self.status | 0b0010_0000) & !STATUS_FLAG_BREAK_COMMAND);
+        self.set_flag(STATUS_FLAG_INTERRUPT_DISABLE);
+
+        self.program_counter = interrupt_vector;
+        return INTERRUPT_CYCLES;
     }
 
     fn update_program_counter(&mut self, instruction: Instruction) {
@@ -262,6 +316,126 @@ mod test_cpu {
         assert_eq!(cpu.status, expected);
     }
 
+    // Program at $8000 with the NMI handler at $9000 and the IRQ/BRK handler at $A000
+    fn interrupt_test_cpu(program: &[u8], status: u8) -> (CPU, Rc<RefCell<TestMapper>>) {
+        let mapper = Rc::new(RefCell::new(TestMapper::new()));
+        mapper.borrow_mut().write_u16(NMI_VECTOR, 0x9000);
+        mapper.borrow_mut().write_u16(IRQ_VECTOR, 0xA000);
+        for (i, byte) in program.iter().enumerate() {
+            mapper.borrow_mut().write_u8(0x8000 + i as u16, *byte);
+        }
+        // every handler starts with a NOP
+        mapper.borrow_mut().write_u8(0x9000, 0xEA);
+        mapper.borrow_mut().write_u8(0xA000, 0xEA);
+
+        let mut cpu = CPU::new(mapper.clone());
+        cpu.program_counter = 0x8000;
+        cpu.status = status;
+        cpu.irq_inhibited = status & STATUS_FLAG_INTERRUPT_DISABLE != 0;
+        return (cpu, mapper);
+    }
+
+    #[test]
+    fn test_irq_is_serviced_when_interrupts_are_enabled() {
+        let (mut cpu, mapper) = interrupt_test_cpu(&[0xEA], 0b0000_0001);
+        mapper.borrow_mut().irq = true;
+        let executed_cycles = cpu.execute_next_instruction().executed_cycles;
+        assert_eq!(INTERRUPT_CYCLES + 2, executed_cycles);
+        assert_eq!(0xA001, cpu.program_counter);
+        assert_eq!(0xFC, cpu.stack_pointer);
+        assert_eq!(0x80, mapper.borrow().read_u8(0x01FF));
+        assert_eq!(0x00, mapper.borrow().read_u8(0x01FE));
+        // the break flag is clear for hardware interrupts
+        assert_eq!(0b0010_0001, mapper.borrow().read_u8(0x01FD));
+        assert_eq!(
+            FlagStates::SET,
+            cpu.get_flag_state(STATUS_FLAG_INTERRUPT_DISABLE)
+        );
+    }
+
+    #[test]
+    fn test_irq_is_ignored_when_interrupts_are_disabled() {
+        let (mut cpu, mapper) = interrupt_test_cpu(&[0xEA], STATUS_FLAG_INTERRUPT_DISABLE);
+        mapper.borrow_mut().irq = true;
+        let executed_cycles = cpu.execute_next_instruction().executed_cycles;
+        assert_eq!(2, executed_cycles);
+        assert_eq!(0x8001, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_irq_is_delayed_by_one_instruction_after_cli() {
+        // CLI, NOP, NOP
+        let (mut cpu, mapper) =
+            interrupt_test_cpu(&[0x58, 0xEA, 0xEA], STATUS_FLAG_INTERRUPT_DISABLE);
+        mapper.borrow_mut().irq = true;
+        cpu.execute_next_instruction();
+        cpu.execute_next_instruction();
+        assert_eq!(0x8002, cpu.program_counter);
+        cpu.execute_next_instruction();
+        assert_eq!(0xA001, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_irq_is_still_serviced_right_after_sei() {
+        // SEI, NOP
+        let (mut cpu, mapper) = interrupt_test_cpu(&[0x78, 0xEA], 0);
+        cpu.execute_next_instruction();
+        mapper.borrow_mut().irq = true;
+        cpu.execute_next_instruction();
+        assert_eq!(0xA001, cpu.program_counter);
+        // the pushed status already has interrupts disabled
+        assert_eq!(0b0010_0100, mapper.borrow().read_u8(0x01FD));
+    }
+
+    #[test]
+    fn test_irq_is_delayed_by_one_instruction_after_plp() {
+        // PLP, NOP, NOP with a cleared status on the stack
+        let (mut cpu, mapper) =
+            interrupt_test_cpu(&[0x28, 0xEA, 0xEA], STATUS_FLAG_INTERRUPT_DISABLE);
+        cpu.stack_push(0);
+        mapper.borrow_mut().irq = true;
+        cpu.execute_next_instruction();
+        cpu.execute_next_instruction();
+        assert_eq!(0x8002, cpu.program_counter);
+        cpu.execute_next_instruction();
+        assert_eq!(0xA001, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_nmi_takes_precedence_over_irq() {
+        let (mut cpu, mapper) = interrupt_test_cpu(&[0xEA], 0);
+        mapper.borrow_mut().irq = true;
+        mapper.borrow().nmi.set(true);
+        let executed_cycles = cpu.execute_next_instruction().executed_cycles;
+        assert_eq!(INTERRUPT_CYCLES + 2, executed_cycles);
+        assert_eq!(0x9001, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_handle_nmi_interrupt() {
+        let (mut cpu, mapper) = interrupt_test_cpu(&[0xEA], 0);
+        assert_eq!(0, cpu.handle_nmi_interrupt());
+        assert_eq!(0x8000, cpu.program_counter);
+        mapper.borrow().nmi.set(true);
+        assert_eq!(INTERRUPT_CYCLES as u8, cpu.handle_nmi_interrupt());
+        assert_eq!(0x9000, cpu.program_counter);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        // BRK, padding byte
+        let (mut cpu, mapper) = interrupt_test_cpu(&[0x00, 0x00], 0b0010_0000);
+        cpu.execute_next_instruction();
+        assert_eq!(0xA000, cpu.program_counter);
+        mapper.borrow().nmi.set(true);
+        cpu.execute_next_instruction();
+        assert_eq!(0x9001, cpu.program_counter);
+        // only the BRK frame is on the stack, with the break flag set
+        assert_eq!(0xFC, cpu.stack_pointer);
+        assert_eq!(0x02, mapper.borrow().read_u8(0x01FE));
+        assert_eq!(0b0011_0000, mapper.borrow().read_u8(0x01FD));
+    }
+
     #[test_case("submodules/65x02/nes6502/v1/00.json")]
     #[test_case("submodules/65x02/nes6502/v1/01.json")]
     #[test_case("submodules/65x02/nes6502/v1/03.json")]
@@ -474,7 +648,7 @@ mod test_cpu {
         let (mut cpu, mapper) = parse_json_value(&test["initial"]);
 
         let executed_cycles = cpu.execute_next_instruction().executed_cycles;
-        let expected_cycles = test["cycles"].members().count() as u8;
+        let expected_cycles = test["cycles"].members().count() as u16;
         assert_eq!(
             executed_cycles, expected_cycles,
             "Executed cycles don't match\n expected: {}\n   actual: {}",
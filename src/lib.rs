@@ -1,5 +1,9 @@
+pub mod apu;
+pub mod cartridge;
 pub mod controller;
 pub mod cpu;
 pub mod memory;
+pub mod nes;
 pub mod ppu;
+pub mod region;
 pub mod rom;
@@ -12,4 +12,14 @@ pub trait Memory {
     fn nmi_occured(&self) -> bool {
         return false;
     }
+
+    // True once after a write to $4014 copied a page to OAM, the CPU is halted while it happens
+    fn dma_occured(&mut self) -> bool {
+        return false;
+    }
+
+    // Level triggered, true while any source is holding the IRQ line low
+    fn irq_asserted(&self) -> bool {
+        return false;
+    }
 }
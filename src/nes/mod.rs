@@ -1,39 +1,329 @@
-use crate::cpu::mappers::*;
-use crate::cpu::*;
-use crate::ppu::*;
+use crate::apu::APU;
+use crate::cartridge;
+use crate::controller::Controller;
+use crate::cpu::mappers::basic_mapper::BasicMapper;
+use crate::cpu::{InstructionResult, CPU};
+use crate::ppu::frame::Frame;
+use crate::ppu::PPU;
+use crate::region::Region;
+use crate::rom::Rom;
 
-use std::sync::{Arc, Mutex};
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
 
-pub fn emulate_game() {
-    // let cpu_bus = Arc::new(Mutex::new(Bus::new()));
-    // let ppu_bus = Arc::new(Mutex::new(Bus::new()));
-    // let mut cpu = CPU::new(cpu_bus.clone());
-    // let gui = GUI::new();
-    // let mut ppu = PPU::new(ppu_bus.clone());
-    // let apu = APU::new();
-    // let controller = Controller::new(&cpu_bus);
+pub struct Nes {
+    rom: Rom,
+    cpu: CPU,
+    ppu: Rc<RefCell<PPU>>,
+    apu: Rc<RefCell<APU>>,
+    controller: Rc<RefCell<Controller>>,
+    mapper: Rc<RefCell<BasicMapper>>,
+    // master clock ticks the CPU ran ahead of the PPU. The NTSC PPU runs exactly 3 dots for
+    // every CPU cycle, on PAL it's 3.2 so the remainder is carried over to the next instruction.
+    master_clock: u32,
+}
+
+impl Nes {
+    pub fn new(rom: Rom) -> Self {
+        let cartridge = cartridge::new(rom.clone());
+        let ppu = Rc::new(RefCell::new(PPU::new(cartridge.clone())));
+        ppu.borrow_mut().region = rom.region;
+        let apu = Rc::new(RefCell::new(APU::new()));
+        apu.borrow_mut().region = rom.region;
+        let controller = Rc::new(RefCell::new(Controller::new()));
+        let mapper = Rc::new(RefCell::new(BasicMapper::new(
+            cartridge,
+            ppu.clone(),
+            apu.clone(),
+            controller.clone(),
+        )));
+        let mut cpu = CPU::new(mapper.clone());
+        cpu.reset();
+
+        Nes {
+            rom,
+            cpu,
+            ppu,
+            apu,
+            controller,
+            mapper,
+            master_clock: 0,
+        }
+    }
+
+    pub fn run_frame(&mut self) -> Ref<'_, Frame> {
+        loop {
+            self.step_instruction();
+            let frame_complete = self.ppu.borrow().frame_complete;
+            if frame_complete {
+                self.ppu.borrow_mut().frame_complete = false;
+                return self.frame();
+            }
+        }
+    }
+
+    pub fn step_instruction(&mut self) -> InstructionResult {
+        let instruction_result = self.cpu.execute_next_instruction();
+
+        let region = self.region();
+        self.master_clock += instruction_result.executed_cycles as u32 * region.cpu_clock_divider();
+        let mut ppu = self.ppu.borrow_mut();
+        while self.master_clock >= region.ppu_clock_divider() {
+            ppu.tick();
+            self.master_clock -= region.ppu_clock_divider();
+        }
+        let mut apu = self.apu.borrow_mut();
+        for _ in 0..instruction_result.executed_cycles {
+            apu.tick();
+        }
+
+        return instruction_result;
+    }
 
-    // let mut ppu_cycles_budet = 0;
-    // let mut apu_cycles_budget = 0;
+    pub fn reset(&mut self) {
+        self.ppu.borrow_mut().reset();
+        self.master_clock = 0;
+        self.apu.borrow_mut().reset();
+        self.cpu.reset();
+    }
 
-    // gui.handle_user_input(&controller, cpu_bus); // update current controller status
+    pub fn power_cycle(&mut self) {
+        // Components are rebuilt in place, so that handles given out by
+        // controller(), ppu(), apu() and mapper() stay valid
+        let cartridge = cartridge::new(self.rom.clone());
+        let palette = self.ppu.borrow().palette.clone();
+        let is_sprite_limit_enabled = self.ppu.borrow().is_sprite_limit_enabled;
+        *self.ppu.borrow_mut() = PPU::new(cartridge.clone());
+        self.ppu.borrow_mut().region = self.rom.region;
+        self.ppu.borrow_mut().palette = palette;
+        self.ppu.borrow_mut().is_sprite_limit_enabled = is_sprite_limit_enabled;
+        self.master_clock = 0;
+        let sample_rate = self.apu.borrow().sample_rate;
+        *self.apu.borrow_mut() = APU::new();
+        self.apu.borrow_mut().region = self.rom.region;
+        self.apu.borrow_mut().sample_rate = sample_rate;
+        *self.mapper.borrow_mut() = BasicMapper::new(
+            cartridge,
+            self.ppu.clone(),
+            self.apu.clone(),
+            self.controller.clone(),
+        );
+        self.cpu = CPU::new(self.mapper.clone());
+        self.cpu.reset();
+    }
 
-    // let executed_cpu_cycles = cpu.execute_next_instruction().executed_cycles;
+    pub fn region(&self) -> Region {
+        return self.rom.region;
+    }
 
-    // ppu_cycles_budet += executed_cpu_cycles * 3;
-    // apu_cycles_budget += executed_cpu_cycles / 2; // this will miss apu cycles, which will probably mess up audio
+    pub fn frame(&self) -> Ref<'_, Frame> {
+        return Ref::map(self.ppu.borrow(), |ppu| &ppu.frame);
+    }
 
-    // for _ in 0..ppu_cycles_budet {
-    //     ppu.tick();
-    // }
+    pub fn controller(&self) -> Rc<RefCell<Controller>> {
+        return self.controller.clone();
+    }
 
-    // for 0..apu_cycles_budget {
-    //     apu.tick();
-    // }
+    pub fn ppu(&self) -> Rc<RefCell<PPU>> {
+        return self.ppu.clone();
+    }
 
-    // gui.render(ppu.frame);
-    // gui.play_sound() // ??????
+    pub fn apu(&self) -> Rc<RefCell<APU>> {
+        return self.apu.clone();
+    }
+
+    pub fn mapper(&self) -> Rc<RefCell<BasicMapper>> {
+        return self.mapper.clone();
+    }
 }
 
-// pub fn load()
-// pub fn reset()
+#[cfg(test)]
+mod test_nes {
+    use super::*;
+    use crate::memory::Memory;
+    use crate::rom::Mirroring;
+
+    // Program that enables NMI and then spins forever. The NMI handler
+    // increments $00 and returns.
+    fn test_rom() -> Rom {
+        return test_rom_with_program(&[
+            0xA9, 0x80, // LDA #$80
+            0x8D, 0x00, 0x20, // STA $2000
+            0x4C, 0x05, 0x80, // JMP $8005
+        ]);
+    }
+
+    fn test_rom_with_program(program: &[u8]) -> Rom {
+        let mut prg_rom = vec![0xEA; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        let nmi_handler = [
+            0xE6, 0x00, // INC $00
+            0x40, // RTI
+        ];
+        prg_rom[0x100..0x100 + nmi_handler.len()].copy_from_slice(&nmi_handler);
+        // NMI vector -> $8100, reset vector -> $8000
+        prg_rom[0x3FFA..].copy_from_slice(&[0x00, 0x81, 0x00, 0x80, 0x00, 0x80]);
+        return Rom::new(prg_rom, vec![], 0, Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_step_instruction_ticks_ppu_three_times_per_cycle() {
+        let mut nes = Nes::new(test_rom());
+        let instruction_result = nes.step_instruction();
+        assert_eq!(2, instruction_result.executed_cycles);
+        assert_eq!(6, nes.ppu.borrow().cycles);
+    }
+
+    #[test]
+    fn test_pal_ticks_ppu_16_times_every_5_cycles() {
+        let mut rom = test_rom_with_program(&[]);
+        rom.region = Region::Pal;
+        let mut nes = Nes::new(rom);
+        // NOP takes 2 cycles, the fractional dots are carried over
+        nes.step_instruction();
+        assert_eq!(6, nes.ppu.borrow().cycles);
+        for _ in 0..4 {
+            nes.step_instruction();
+        }
+        assert_eq!(32, nes.ppu.borrow().cycles);
+    }
+
+    #[test]
+    fn test_reset_drops_fractional_pal_dots() {
+        let mut rom = test_rom_with_program(&[]);
+        rom.region = Region::Pal;
+        let mut nes = Nes::new(rom);
+        // two NOPs leave 4/5 of a dot behind
+        nes.step_instruction();
+        nes.step_instruction();
+        nes.reset();
+        nes.step_instruction();
+        assert_eq!(6, nes.ppu.borrow().cycles);
+    }
+
+    #[test]
+    fn test_pal_frame_has_312_scanlines() {
+        let mut rom = test_rom();
+        rom.region = Region::Pal;
+        let mut nes = Nes::new(rom);
+        nes.run_frame();
+        nes.run_frame();
+        let ppu = nes.ppu();
+        let frame_count = ppu.borrow().frame_count;
+        let mut max_scanline = 0;
+        while ppu.borrow().frame_count == frame_count {
+            nes.step_instruction();
+            max_scanline = max_scanline.max(ppu.borrow().scanline);
+        }
+        assert_eq!(311, max_scanline);
+    }
+
+    #[test]
+    fn test_run_frame_runs_until_vblank() {
+        let mut nes = Nes::new(test_rom());
+        nes.run_frame();
+        assert!(nes.ppu.borrow().scanline > 240);
+        nes.run_frame();
+        nes.run_frame();
+        // the v-blank flag is set at power-up, so enabling NMI right away triggers one,
+        // and the nmi of the last frame is handled on the next instruction
+        assert_eq!(3, nes.mapper.borrow().read_u8(0x0000));
+    }
+
+    #[test]
+    fn test_oam_dma_stalls_cpu() {
+        let mut nes = Nes::new(test_rom_with_program(&[
+            0xA9, 0x80, // LDA #$80
+            0x8D, 0x14, 0x40, // STA $4014
+            0xA9, 0x80, // LDA #$80
+            0x8D, 0x14, 0x40, // STA $4014
+        ]));
+        nes.step_instruction();
+        // the DMA starts on an even cycle
+        assert_eq!(4 + 513, nes.step_instruction().executed_cycles);
+        nes.step_instruction();
+        // and now on an odd one
+        assert_eq!(4 + 514, nes.step_instruction().executed_cycles);
+
+        // the page was copied from PRG ROM
+        let ppu = nes.ppu();
+        ppu.borrow_mut().write_oam_address(0);
+        assert_eq!(0xA9, ppu.borrow_mut().read_oam_data());
+    }
+
+    #[test]
+    fn test_oam_dma_from_io_page() {
+        let mut nes = Nes::new(test_rom_with_program(&[
+            0xA9, 0x40, // LDA #$40
+            0x8D, 0x14, 0x40, // STA $4014
+        ]));
+        let ppu = nes.ppu();
+        for _ in 0..256 {
+            ppu.borrow_mut().write_oam_data(0xFF);
+        }
+        nes.step_instruction();
+        nes.step_instruction();
+
+        // $4018-$401F are unmapped and read as 0
+        for address in 0x18..=0x1F {
+            ppu.borrow_mut().write_oam_address(address);
+            assert_eq!(0, ppu.borrow_mut().read_oam_data());
+        }
+    }
+
+    #[test]
+    fn test_apu_registers_are_on_the_bus() {
+        let nes = Nes::new(test_rom());
+        let mapper = nes.mapper();
+        mapper.borrow_mut().write_u8(0x4015, 0b0000_0010);
+        mapper.borrow_mut().write_u8(0x4007, 0b0000_1000);
+        assert_eq!(0b0000_0010, mapper.borrow().read_u8(0x4015));
+    }
+
+    #[test]
+    fn test_apu_frame_interrupt_asserts_irq() {
+        let mut nes = Nes::new(test_rom());
+        nes.run_frame();
+        nes.run_frame();
+        assert!(nes.mapper().borrow().irq_asserted());
+        nes.mapper().borrow().read_u8(0x4015);
+        assert!(!nes.mapper().borrow().irq_asserted());
+    }
+
+    #[test]
+    fn test_power_cycle_keeps_handles_valid() {
+        let mut nes = Nes::new(test_rom());
+        let ppu = nes.ppu();
+        nes.run_frame();
+        nes.power_cycle();
+        assert_eq!(0, ppu.borrow().scanline);
+        assert_eq!(0, nes.mapper().borrow().read_u8(0x0000));
+    }
+
+    #[test]
+    fn test_power_cycle_keeps_ppu_settings() {
+        let mut nes = Nes::new(test_rom());
+        nes.ppu().borrow_mut().is_sprite_limit_enabled = false;
+        nes.power_cycle();
+        assert!(!nes.ppu().borrow().is_sprite_limit_enabled);
+    }
+
+    #[test]
+    fn test_mmc1_is_on_the_bus() {
+        // every 16KB PRG bank is filled with its own number
+        let prg_rom = (0..8).flat_map(|bank| vec![bank as u8; 0x4000]).collect();
+        let nes = Nes::new(Rom::new(prg_rom, vec![], 1, Mirroring::Vertical));
+        let mapper = nes.mapper();
+        assert_eq!(0, mapper.borrow().read_u8(0x8000));
+        assert_eq!(7, mapper.borrow().read_u8(0xC000));
+
+        // the PRG bank register is loaded one bit at a time
+        for bit in 0..5 {
+            mapper.borrow_mut().write_u8(0xE000, (3 >> bit) & 1);
+        }
+        assert_eq!(3, mapper.borrow().read_u8(0x8000));
+
+        mapper.borrow_mut().write_u8(0x6000, 0xAA);
+        assert_eq!(0xAA, mapper.borrow().read_u8(0x6000));
+    }
+}
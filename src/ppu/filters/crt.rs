@@ -0,0 +1,69 @@
+use crate::ppu::filters::Image;
+
+// Darkens the last row of every frame line, or every other row when the image isn't scaled
+pub fn scanlines(image: &mut Image, intensity: f64) {
+    for y in 0..image.height {
+        let is_gap = match image.scale {
+            1 => y % 2 == 1,
+            scale => y % scale == scale - 1,
+        };
+        if !is_gap {
+            continue;
+        }
+        for x in 0..image.width {
+            let (red, green, blue) = image.pixel(x as isize, y as isize);
+            let darken = |value: u8| (value as f64 * (1.0 - intensity)).round() as u8;
+            image.set_pixel(x, y, (darken(red), darken(green), darken(blue)));
+        }
+    }
+}
+
+// Every column only lets one of red, green and blue through, like the stripes of a Trinitron
+pub fn aperture_grille(image: &mut Image, intensity: f64) {
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let (red, green, blue) = image.pixel(x as isize, y as isize);
+            let stripe = x % 3;
+            let mask = |value: u8, channel: usize| match channel == stripe {
+                true => value,
+                false => (value as f64 * (1.0 - intensity)).round() as u8,
+            };
+            image.set_pixel(x, y, (mask(red, 0), mask(green, 1), mask(blue, 2)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_crt {
+    use super::*;
+
+    fn white(width: usize, height: usize, scale: usize) -> Image {
+        let mut image = Image::new(width, height, scale);
+        image.bytes.fill(200);
+        return image;
+    }
+
+    #[test]
+    fn test_scanlines() {
+        let mut image = white(3, 6, 3);
+        scanlines(&mut image, 0.5);
+        assert_eq!((200, 200, 200), image.pixel(0, 1));
+        assert_eq!((100, 100, 100), image.pixel(0, 2));
+        assert_eq!((100, 100, 100), image.pixel(2, 5));
+
+        let mut image = white(2, 2, 1);
+        scanlines(&mut image, 1.0);
+        assert_eq!((200, 200, 200), image.pixel(0, 0));
+        assert_eq!((0, 0, 0), image.pixel(0, 1));
+    }
+
+    #[test]
+    fn test_aperture_grille() {
+        let mut image = white(4, 1, 1);
+        aperture_grille(&mut image, 0.25);
+        assert_eq!((200, 150, 150), image.pixel(0, 0));
+        assert_eq!((150, 200, 150), image.pixel(1, 0));
+        assert_eq!((150, 150, 200), image.pixel(2, 0));
+        assert_eq!((200, 150, 150), image.pixel(3, 0));
+    }
+}
@@ -0,0 +1,49 @@
+// Post-processing of finished frames, everything runs on the CPU
+pub mod crt;
+pub mod ntsc;
+pub mod scale;
+
+use crate::ppu::frame::{Frame, HEIGHT, WIDTH};
+
+// RGB24 image, scale is the number of pixels every frame pixel covers in each direction
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub scale: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, scale: usize) -> Self {
+        Image {
+            width: width,
+            height: height,
+            scale: scale,
+            bytes: vec![0; width * height * 3],
+        }
+    }
+
+    pub fn from_frame(frame: &Frame) -> Self {
+        Image {
+            width: WIDTH,
+            height: HEIGHT,
+            scale: 1,
+            bytes: frame.bytes.to_vec(),
+        }
+    }
+
+    // pixels outside of the image repeat the closest edge pixel
+    pub fn pixel(&self, x: isize, y: isize) -> (u8, u8, u8) {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        let base = (y * self.width + x) * 3;
+        return (self.bytes[base], self.bytes[base + 1], self.bytes[base + 2]);
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+        let base = (y * self.width + x) * 3;
+        self.bytes[base] = rgb.0;
+        self.bytes[base + 1] = rgb.1;
+        self.bytes[base + 2] = rgb.2;
+    }
+}
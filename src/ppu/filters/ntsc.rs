@@ -0,0 +1,205 @@
+use crate::ppu::frame::{Frame, HEIGHT, WIDTH};
+use crate::ppu::palette::{carrier_angle, signal_level, yiq_to_rgb};
+
+// https://www.nesdev.org/wiki/NTSC_video
+// Every pixel is 8 samples of the composite signal, and the color carrier repeats every 12.
+const SAMPLES_PER_PIXEL: usize = 8;
+const SAMPLES_PER_CYCLE: usize = 12;
+// one output pixel is decoded for every 4 samples
+const SAMPLES_PER_OUTPUT_PIXEL: usize = 4;
+pub const NTSC_WIDTH: usize = WIDTH * SAMPLES_PER_PIXEL / SAMPLES_PER_OUTPUT_PIXEL;
+// a scanline is 341 pixels long, so the carrier phase moves by 4 samples every line
+const LINE_PHASE_SHIFT: usize = 341 * SAMPLES_PER_PIXEL % SAMPLES_PER_CYCLE;
+
+const PIXEL_VALUES: usize = 512;
+
+// Encodes a frame into the composite signal the PPU would output and decodes it again like a TV,
+// which brings back the color artifacts and the dot crawl of the real console.
+pub struct NtscFilter {
+    // 0 leaves luma as blurry as a whole color cycle, higher values boost the edges between pixels
+    pub sharpness: f64,
+    // 0 removes the colors that bleed out of luma edges, 1 keeps all of them
+    pub fringing: f64,
+    // signal level of every pixel value at every phase of the carrier
+    levels: Vec<[f64; SAMPLES_PER_CYCLE]>,
+    // the colors decoded from an uninterrupted signal of a single pixel value
+    clean_colors: Vec<(f64, f64, f64)>,
+    // cosine and sine of the color carrier at each phase
+    carrier: [(f64, f64); SAMPLES_PER_CYCLE],
+}
+
+impl NtscFilter {
+    pub fn new() -> Self {
+        let levels: Vec<[f64; SAMPLES_PER_CYCLE]> = (0..PIXEL_VALUES)
+            .map(|pixel| {
+                let mut levels = [0.0; SAMPLES_PER_CYCLE];
+                for (phase, level) in levels.iter_mut().enumerate() {
+                    *level = signal_level((pixel & 0x3F) as u8, (pixel >> 6) as u8, phase as u8);
+                }
+                levels
+            })
+            .collect();
+        let mut carrier = [(0.0, 0.0); SAMPLES_PER_CYCLE];
+        for (phase, (cos, sin)) in carrier.iter_mut().enumerate() {
+            let angle = carrier_angle(phase as u8);
+            (*cos, *sin) = (angle.cos(), angle.sin());
+        }
+
+        let mut filter = NtscFilter {
+            sharpness: 0.5,
+            fringing: 1.0,
+            levels: levels,
+            clean_colors: vec![],
+            carrier: carrier,
+        };
+        filter.clean_colors = filter
+            .levels
+            .iter()
+            .map(|levels| filter.demodulate(levels, 0))
+            .collect();
+        return filter;
+    }
+
+    // Returns NTSC_WIDTH x HEIGHT RGB24 bytes. The frame count moves the carrier phase,
+    // passing the same value every time stops the dot crawl.
+    pub fn apply(&self, frame: &Frame, frame_count: u64) -> Vec<u8> {
+        let frame_phase = (frame_count % 3) as usize * LINE_PHASE_SHIFT;
+        let mut result = Vec::with_capacity(NTSC_WIDTH * HEIGHT * 3);
+        let mut signal = vec![0.0; WIDTH * SAMPLES_PER_PIXEL];
+
+        for y in 0..HEIGHT {
+            let line_phase = (frame_phase + y * LINE_PHASE_SHIFT) % SAMPLES_PER_CYCLE;
+            let pixels = &frame.pixels[y * WIDTH..(y + 1) * WIDTH];
+            for (sample, level) in signal.iter_mut().enumerate() {
+                let pixel = pixels[sample / SAMPLES_PER_PIXEL] as usize % PIXEL_VALUES;
+                *level = self.levels[pixel][(line_phase + sample) % SAMPLES_PER_CYCLE];
+            }
+
+            for x in 0..NTSC_WIDTH {
+                let center = x * SAMPLES_PER_OUTPUT_PIXEL + SAMPLES_PER_OUTPUT_PIXEL / 2;
+                let pixel = pixels[x * SAMPLES_PER_OUTPUT_PIXEL / SAMPLES_PER_PIXEL] as usize;
+                let rgb = self.decode(&signal, center, line_phase, pixel % PIXEL_VALUES);
+                result.extend_from_slice(&[rgb.0, rgb.1, rgb.2]);
+            }
+        }
+        return result;
+    }
+
+    fn decode(
+        &self,
+        signal: &[f64],
+        center: usize,
+        line_phase: usize,
+        pixel: usize,
+    ) -> (u8, u8, u8) {
+        // one color cycle around a sample, the signal is black outside of the picture
+        let cycle_around = |center: isize| {
+            let start = center - SAMPLES_PER_CYCLE as isize / 2;
+            let mut samples = [0.0; SAMPLES_PER_CYCLE];
+            for (i, level) in samples.iter_mut().enumerate() {
+                let sample = start + i as isize;
+                if sample >= 0 && (sample as usize) < signal.len() {
+                    *level = signal[sample as usize];
+                }
+            }
+            let start_phase = (line_phase as isize + start).rem_euclid(SAMPLES_PER_CYCLE as isize);
+            return self.demodulate(&samples, start_phase as usize);
+        };
+
+        let center = center as isize;
+        let (luma, i, q) = cycle_around(center);
+        // unsharp mask against the luma of the neighbouring output pixels
+        let step = SAMPLES_PER_OUTPUT_PIXEL as isize;
+        let (left_luma, _, _) = cycle_around(center - step);
+        let (right_luma, _, _) = cycle_around(center + step);
+        let y = luma + (2.0 * luma - left_luma - right_luma) / 2.0 * self.sharpness;
+
+        let (_, clean_i, clean_q) = self.clean_colors[pixel];
+        let i = clean_i + (i - clean_i) * self.fringing;
+        let q = clean_q + (q - clean_q) * self.fringing;
+        return yiq_to_rgb(y, i, q);
+    }
+
+    // Splits one color cycle of the signal into luma and the two color components
+    fn demodulate(&self, cycle: &[f64; SAMPLES_PER_CYCLE], start_phase: usize) -> (f64, f64, f64) {
+        let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+        for (sample, level) in cycle.iter().enumerate() {
+            let (cos, sin) = self.carrier[(start_phase + sample) % SAMPLES_PER_CYCLE];
+            y += level;
+            i += level * cos;
+            q += level * sin;
+        }
+        let samples = SAMPLES_PER_CYCLE as f64;
+        return (y / samples, i / samples, q / samples);
+    }
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        return NtscFilter::new();
+    }
+}
+
+#[cfg(test)]
+mod test_ntsc {
+    use super::*;
+    use crate::ppu::palette::Palette;
+
+    fn frame_with(pixel: impl Fn(usize, usize) -> u16) -> Frame {
+        let mut frame = Frame::new();
+        let palette = Palette::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                frame.set_pixel(x, y, pixel(x, y), &palette);
+            }
+        }
+        return frame;
+    }
+
+    fn rgb(image: &[u8], x: usize, y: usize) -> (u8, u8, u8) {
+        let base = (y * NTSC_WIDTH + x) * 3;
+        return (image[base], image[base + 1], image[base + 2]);
+    }
+
+    #[test]
+    fn test_solid_color_decodes_to_generated_palette() {
+        let filter = NtscFilter::new();
+        let image = filter.apply(&frame_with(|_, _| 0x16), 0);
+        assert_eq!(NTSC_WIDTH * HEIGHT * 3, image.len());
+
+        let expected = Palette::generate(0.0, 1.0, 1.0, 0.0).color(0x16, 0);
+        let (red, green, blue) = rgb(&image, 100, 100);
+        let is_close = |a: u8, b: u8| (a as i16 - b as i16).abs() <= 1;
+        assert!(
+            is_close(expected.0, red) && is_close(expected.1, green) && is_close(expected.2, blue)
+        );
+    }
+
+    #[test]
+    fn test_luma_edges_produce_artifact_colors_that_crawl() {
+        // alternating black and white columns
+        let frame = frame_with(|x, _| if x % 2 == 0 { 0x30 } else { 0x0F });
+        let mut filter = NtscFilter::new();
+        let image = filter.apply(&frame, 0);
+        let (red, green, blue) = rgb(&image, 101, 100);
+        assert!(red != green || green != blue);
+        assert_ne!(image, filter.apply(&frame, 1));
+
+        filter.fringing = 0.0;
+        let image = filter.apply(&frame, 0);
+        let (red, green, blue) = rgb(&image, 101, 100);
+        assert!(red == green && green == blue);
+    }
+
+    #[test]
+    fn test_sharpness_keeps_edges() {
+        let frame = frame_with(|x, _| if x < 128 { 0x10 } else { 0x00 });
+        let mut filter = NtscFilter::new();
+        filter.sharpness = 0.0;
+        let soft = rgb(&filter.apply(&frame, 0), 254, 10);
+        filter.sharpness = 1.0;
+        let sharp = rgb(&filter.apply(&frame, 0), 254, 10);
+        // the light side of the edge gets brighter
+        assert!(sharp.1 > soft.1);
+    }
+}
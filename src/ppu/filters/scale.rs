@@ -0,0 +1,272 @@
+use crate::ppu::filters::Image;
+use crate::ppu::frame::Frame;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaler {
+    Nearest,
+    // Scale2x and Scale3x, also known as EPX and AdvMAME. 4x runs Scale2x twice.
+    ScaleNx,
+    Xbr,
+}
+
+// Scales a frame by 2, 3 or 4
+pub fn scale(frame: &Frame, scaler: Scaler, factor: usize) -> Image {
+    assert!(
+        (2..=4).contains(&factor),
+        "Unsupported scale factor {}",
+        factor
+    );
+    let image = Image::from_frame(frame);
+    return match (scaler, factor) {
+        (Scaler::Nearest, _) => nearest(&image, factor),
+        (Scaler::ScaleNx, 2) => scale2x(&image),
+        (Scaler::ScaleNx, 3) => scale3x(&image),
+        (Scaler::ScaleNx, _) => scale2x(&scale2x(&image)),
+        (Scaler::Xbr, _) => xbr(&image, factor),
+    };
+}
+
+pub fn nearest(image: &Image, factor: usize) -> Image {
+    let mut result = Image::new(
+        image.width * factor,
+        image.height * factor,
+        image.scale * factor,
+    );
+    for y in 0..result.height {
+        for x in 0..result.width {
+            let rgb = image.pixel((x / factor) as isize, (y / factor) as isize);
+            result.set_pixel(x, y, rgb);
+        }
+    }
+    return result;
+}
+
+// https://www.scale2x.it/algorithm
+//   A B C
+//   D E F
+//   G H I
+pub fn scale2x(image: &Image) -> Image {
+    let mut result = nearest(image, 2);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let at = |dx: isize, dy: isize| image.pixel(x as isize + dx, y as isize + dy);
+            let (b, d, e, f, h) = (at(0, -1), at(-1, 0), at(0, 0), at(1, 0), at(0, 1));
+            if b == h || d == f {
+                continue;
+            }
+
+            let block = [
+                if d == b { d } else { e },
+                if b == f { f } else { e },
+                if d == h { d } else { e },
+                if h == f { f } else { e },
+            ];
+            for (i, rgb) in block.iter().enumerate() {
+                result.set_pixel(x * 2 + i % 2, y * 2 + i / 2, *rgb);
+            }
+        }
+    }
+    return result;
+}
+
+pub fn scale3x(image: &Image) -> Image {
+    let mut result = nearest(image, 3);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let at = |dx: isize, dy: isize| image.pixel(x as isize + dx, y as isize + dy);
+            let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+            let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+            let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+            if b == h || d == f {
+                continue;
+            }
+
+            let block = [
+                if d == b { d } else { e },
+                if (d == b && e != c) || (b == f && e != a) {
+                    b
+                } else {
+                    e
+                },
+                if b == f { f } else { e },
+                if (d == b && e != g) || (d == h && e != a) {
+                    d
+                } else {
+                    e
+                },
+                e,
+                if (b == f && e != i) || (h == f && e != c) {
+                    f
+                } else {
+                    e
+                },
+                if d == h { d } else { e },
+                if (d == h && e != i) || (h == f && e != g) {
+                    h
+                } else {
+                    e
+                },
+                if h == f { f } else { e },
+            ];
+            for (n, rgb) in block.iter().enumerate() {
+                result.set_pixel(x * 3 + n % 3, y * 3 + n / 3, *rgb);
+            }
+        }
+    }
+    return result;
+}
+
+// https://forums.libretro.com/t/xbr-algorithm-tutorial/123
+// Only the first level of xBR: every corner of a pixel looks for a diagonal edge running across
+// it, and the part of the block beyond the edge is blended towards the color on the other side.
+// The 4 corners are handled by mirroring the offsets of the bottom right one.
+pub fn xbr(image: &Image, factor: usize) -> Image {
+    let mut result = nearest(image, factor);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            for (mirror_x, mirror_y) in [(1, 1), (-1, 1), (1, -1), (-1, -1)] {
+                let at = |dx: isize, dy: isize| {
+                    image.pixel(x as isize + dx * mirror_x, y as isize + dy * mirror_y)
+                };
+                let (e, f, h, i) = (at(0, 0), at(1, 0), at(0, 1), at(1, 1));
+                if e == f || e == h {
+                    continue;
+                }
+
+                let (b, c, d, g) = (at(0, -1), at(1, -1), at(-1, 0), at(-1, 1));
+                let (f4, h5, i4, i5) = (at(2, 0), at(0, 2), at(2, 1), at(1, 2));
+                let edge_weight = distance(e, c)
+                    + distance(e, g)
+                    + distance(i, f4)
+                    + distance(i, h5)
+                    + 4 * distance(h, f);
+                let across_weight = distance(h, d)
+                    + distance(h, i5)
+                    + distance(f, i4)
+                    + distance(f, b)
+                    + 4 * distance(e, i);
+                if edge_weight >= across_weight {
+                    continue;
+                }
+
+                let color = if distance(e, f) <= distance(e, h) {
+                    f
+                } else {
+                    h
+                };
+                for sub_y in 0..factor {
+                    for sub_x in 0..factor {
+                        // how far the center of the sub pixel is past the edge
+                        let position = (sub_x + sub_y + 1) as f64 / factor as f64;
+                        let weight = (position - 1.0).clamp(0.0, 1.0);
+                        if weight == 0.0 {
+                            continue;
+                        }
+
+                        let out_x = match mirror_x {
+                            1 => x * factor + sub_x,
+                            _ => x * factor + factor - 1 - sub_x,
+                        };
+                        let out_y = match mirror_y {
+                            1 => y * factor + sub_y,
+                            _ => y * factor + factor - 1 - sub_y,
+                        };
+                        let current = result.pixel(out_x as isize, out_y as isize);
+                        result.set_pixel(out_x, out_y, blend(current, color, weight));
+                    }
+                }
+            }
+        }
+    }
+    return result;
+}
+
+// Difference of two colors in YUV, weighted towards luma like xBR does
+fn distance(first: (u8, u8, u8), second: (u8, u8, u8)) -> u32 {
+    let difference = |a: u8, b: u8| a as f64 - b as f64;
+    let (red, green, blue) = (
+        difference(first.0, second.0),
+        difference(first.1, second.1),
+        difference(first.2, second.2),
+    );
+    let y = 0.299 * red + 0.587 * green + 0.114 * blue;
+    let u = -0.169 * red - 0.331 * green + 0.5 * blue;
+    let v = 0.5 * red - 0.419 * green - 0.081 * blue;
+    return (48.0 * y.abs() + 7.0 * u.abs() + 6.0 * v.abs()) as u32;
+}
+
+fn blend(first: (u8, u8, u8), second: (u8, u8, u8), weight: f64) -> (u8, u8, u8) {
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * weight).round() as u8;
+    return (
+        mix(first.0, second.0),
+        mix(first.1, second.1),
+        mix(first.2, second.2),
+    );
+}
+
+#[cfg(test)]
+mod test_scale {
+    use super::*;
+
+    const BLACK: (u8, u8, u8) = (0, 0, 0);
+    const WHITE: (u8, u8, u8) = (255, 255, 255);
+
+    // white below the diagonal from the top left to the bottom right corner
+    fn diagonal(size: usize) -> Image {
+        let mut image = Image::new(size, size, 1);
+        for y in 0..size {
+            for x in 0..size {
+                image.set_pixel(x, y, if x < y { WHITE } else { BLACK });
+            }
+        }
+        return image;
+    }
+
+    #[test]
+    fn test_scale_sizes() {
+        let frame = Frame::new();
+        for (scaler, factor) in [(Scaler::Nearest, 4), (Scaler::ScaleNx, 4), (Scaler::Xbr, 3)] {
+            let image = scale(&frame, scaler, factor);
+            assert_eq!((256 * factor, 240 * factor), (image.width, image.height));
+            assert_eq!(factor, image.scale);
+            assert_eq!(256 * factor * 240 * factor * 3, image.bytes.len());
+        }
+    }
+
+    #[test]
+    fn test_nearest() {
+        let image = nearest(&diagonal(4), 2);
+        assert_eq!(WHITE, image.pixel(2, 4));
+        assert_eq!(WHITE, image.pixel(3, 5));
+        assert_eq!(BLACK, image.pixel(2, 3));
+    }
+
+    #[test]
+    fn test_scale2x_smooths_diagonals() {
+        let image = scale2x(&diagonal(4));
+        // the black pixel at (1, 1) takes the white color in its bottom left corner
+        assert_eq!(WHITE, image.pixel(2, 3));
+        assert_eq!(BLACK, image.pixel(3, 2));
+        assert_eq!(BLACK, image.pixel(2, 2));
+    }
+
+    #[test]
+    fn test_scale3x_smooths_diagonals() {
+        let image = scale3x(&diagonal(4));
+        assert_eq!(WHITE, image.pixel(3, 5));
+        assert_eq!(BLACK, image.pixel(4, 4));
+        assert_eq!(BLACK, image.pixel(5, 3));
+    }
+
+    #[test]
+    fn test_xbr_blends_diagonals() {
+        let image = xbr(&diagonal(6), 4);
+        // the bottom left corner of the black pixel at (2, 2) moves towards white
+        let (red, _, _) = image.pixel(8, 11);
+        assert!(red > 0 && red < 255);
+        assert_eq!(BLACK, image.pixel(11, 8));
+        // flat areas stay the same
+        assert_eq!(BLACK, image.pixel(20, 1));
+        assert_eq!(WHITE, image.pixel(1, 20));
+    }
+}
@@ -1,25 +1,74 @@
-const WIDTH: usize = 256;
-const HEIGHT: usize = 240;
+use crate::ppu::palette::Palette;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
 // width is multiplied by 3, because each pixel needs 3 u8 values to represent its RGB colors
 const SIZE: usize = WIDTH * 3 * HEIGHT;
 
 pub struct Frame {
     pub bytes: [u8; SIZE],
+    // palette index in bits 0-5 and the emphasis bits in 6-8, so the colors can be derived again
+    pub pixels: [u16; WIDTH * HEIGHT],
 }
 
 impl Frame {
     pub fn new() -> Self {
-        Frame { bytes: [0; SIZE] }
+        Frame {
+            bytes: [0; SIZE],
+            pixels: [0; WIDTH * HEIGHT],
+        }
     }
 
-    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+    pub fn set_pixel(&mut self, x: usize, y: usize, pixel: u16, palette: &Palette) {
         let base = y * 3 * WIDTH + x * 3;
         assert!(base + 2 < self.bytes.len());
 
+        let rgb = palette.pixel_color(pixel);
+        self.pixels[y * WIDTH + x] = pixel;
         self.bytes[base] = rgb.0;
         self.bytes[base + 1] = rgb.1;
         self.bytes[base + 2] = rgb.2;
     }
+
+    fn colors<'a>(&'a self, palette: &'a Palette) -> impl Iterator<Item = (u8, u8, u8)> + 'a {
+        return self.pixels.iter().map(|pixel| palette.pixel_color(*pixel));
+    }
+
+    pub fn to_rgb24(&self, palette: &Palette) -> Vec<u8> {
+        return self
+            .colors(palette)
+            .flat_map(|(red, green, blue)| [red, green, blue])
+            .collect();
+    }
+
+    pub fn to_rgba8888(&self, palette: &Palette) -> Vec<u8> {
+        return self
+            .colors(palette)
+            .flat_map(|(red, green, blue)| [red, green, blue, 0xFF])
+            .collect();
+    }
+
+    pub fn to_bgra8888(&self, palette: &Palette) -> Vec<u8> {
+        return self
+            .colors(palette)
+            .flat_map(|(red, green, blue)| [blue, green, red, 0xFF])
+            .collect();
+    }
+
+    pub fn to_rgb565(&self, palette: &Palette) -> Vec<u16> {
+        return self
+            .colors(palette)
+            .map(|(red, green, blue)| {
+                (red as u16 >> 3) << 11 | (green as u16 >> 2) << 5 | blue as u16 >> 3
+            })
+            .collect();
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        return Frame::new();
+    }
 }
 
 #[cfg(test)]
@@ -38,4 +87,37 @@ mod test_frame {
         let frame = Frame::new();
         assert_eq!(256 * 3 * 240, frame.bytes.len());
     }
+
+    #[test]
+    fn test_set_pixel_keeps_palette_index_and_emphasis() {
+        let mut frame = Frame::new();
+        let palette = Palette::new();
+        frame.set_pixel(1, 2, 0x16 | 0b001 << 6, &palette);
+        assert_eq!(0x16 | 0b001 << 6, frame.pixels[2 * 256 + 1]);
+        let base = (2 * 256 + 1) * 3;
+        assert_eq!(
+            palette.color(0x16, 0b001),
+            (
+                frame.bytes[base],
+                frame.bytes[base + 1],
+                frame.bytes[base + 2]
+            )
+        );
+        assert_eq!(
+            frame.bytes[base..base + 3],
+            frame.to_rgb24(&palette)[base..base + 3]
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        let mut frame = Frame::new();
+        let palette = Palette::try_from(&vec![0xFF, 0x80, 0x08].repeat(64)).unwrap();
+        frame.set_pixel(0, 0, 0x01, &palette);
+        assert_eq!([0xFF, 0x80, 0x08, 0xFF], frame.to_rgba8888(&palette)[..4]);
+        assert_eq!([0x08, 0x80, 0xFF, 0xFF], frame.to_bgra8888(&palette)[..4]);
+        // red 0x1F, green 0x20 and blue 0x01
+        assert_eq!(0b1111_1100_0000_0001, frame.to_rgb565(&palette)[0]);
+        assert_eq!(256 * 240, frame.to_rgb565(&palette).len());
+    }
 }
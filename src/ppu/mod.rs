@@ -1,84 +1,137 @@
-mod frame;
+pub mod filters;
+pub mod frame;
+pub mod palette;
 mod registers;
 use core::panic;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::cartridge::Cartridge;
+use crate::region::Region;
 
 use registers::data::Data;
 use registers::write_toggle::WriteToggle;
 use registers::{Register16, Register8};
 
 use frame::Frame;
+use palette::Palette;
+pub use palette::SYSTEM_PALETTE;
 use registers::address::Address;
 use registers::control::Control;
 use registers::mask::Mask;
 use registers::status::Status;
 
 use registers::oam_address::OAMAddress;
-#[rustfmt::skip]
-pub static SYSTEM_PALETTE: [(u8,u8,u8); 64] = [
-   (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96), 
-   (0xA1, 0x00, 0x5E), (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00),
-   (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00), (0x05, 0x4A, 0x00), (0x00, 0x47, 0x2E),
-   (0x00, 0x41, 0x66), (0x00, 0x00, 0x00), (0x05, 0x05, 0x05), (0x05, 0x05, 0x05),
-   (0xC7, 0xC7, 0xC7), (0x00, 0x77, 0xFF), (0x21, 0x55, 0xFF), (0x82, 0x37, 0xFA),
-   (0xEB, 0x2F, 0xB5), (0xFF, 0x29, 0x50), (0xFF, 0x22, 0x00), (0xD6, 0x32, 0x00),
-   (0xC4, 0x62, 0x00), (0x35, 0x80, 0x00), (0x05, 0x8F, 0x00), (0x00, 0x8A, 0x55),
-   (0x00, 0x99, 0xCC), (0x21, 0x21, 0x21), (0x09, 0x09, 0x09), (0x09, 0x09, 0x09),
-   (0xFF, 0xFF, 0xFF), (0x0F, 0xD7, 0xFF), (0x69, 0xA2, 0xFF), (0xD4, 0x80, 0xFF),
-   (0xFF, 0x45, 0xF3), (0xFF, 0x61, 0x8B), (0xFF, 0x88, 0x33), (0xFF, 0x9C, 0x12),
-   (0xFA, 0xBC, 0x20), (0x9F, 0xE3, 0x0E), (0x2B, 0xF0, 0x35), (0x0C, 0xF0, 0xA4),
-   (0x05, 0xFB, 0xFF), (0x5E, 0x5E, 0x5E), (0x0D, 0x0D, 0x0D), (0x0D, 0x0D, 0x0D),
-   (0xFF, 0xFF, 0xFF), (0xA6, 0xFC, 0xFF), (0xB3, 0xEC, 0xFF), (0xDA, 0xAB, 0xEB),
-   (0xFF, 0xA8, 0xF9), (0xFF, 0xAB, 0xB3), (0xFF, 0xD2, 0xB0), (0xFF, 0xEF, 0xA6),
-   (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
-   (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11)
-];
+
+// https://www.nesdev.org/wiki/PPU_frame_timing
+// the number of scanlines depends on the region
+const DOTS_PER_SCANLINE: u16 = 341;
+// bits of the I/O latch fade to 0 after roughly 600ms without being refreshed
+const IO_LATCH_DECAY_FRAMES: u8 = 36;
 
 pub struct PPU {
     control: Control,
     mask: Mask,
     status: Status,
     oamaddr: OAMAddress,
-    oamdata: u16,
-    ppuscroll: u16,
+    // current (v) and temporary (t) VRAM address, and fine x scroll
     address: Address,
+    temp_address: Address,
+    fine_x: u8,
     data: Data,
     pub frame: Frame,
     pub vram: [u8; 2048],
     oam_ram: [u8; 256],
     palette_ram: [u8; 32],
+    // https://www.nesdev.org/wiki/Open_bus_behavior#PPU_open_bus
+    // value left on the data bus between the CPU and the PPU, and the frames since each bit was driven
+    io_latch: u8,
+    io_latch_age: [u8; 8],
     w: WriteToggle,
     pub cycles: u16,
     pub scanline: u16,
+    pub region: Region,
+    pub palette: Palette,
+    // frames started since power-up, odd frames are one dot shorter while rendering
+    pub frame_count: u64,
     pub nmi_triggered: bool,
-    chr: Vec<u8>,
+    pub frame_complete: bool,
+    // when disabled every sprite on a line is drawn, which avoids flicker at the cost of accuracy
+    pub is_sprite_limit_enabled: bool,
+    // sprites found for the next line during evaluation
+    secondary_oam: Vec<[u8; 4]>,
+    is_sprite_zero_on_line: bool,
+    // https://www.nesdev.org/wiki/PPU_rendering#Preface
+    // bytes fetched for the next tile, and the shift registers holding the current and next tile
+    next_tile_index: u8,
+    next_tile_attribute: u8,
+    next_tile_low: u8,
+    next_tile_high: u8,
+    pattern_shift_low: u16,
+    pattern_shift_high: u16,
+    attribute_shift_low: u16,
+    attribute_shift_high: u16,
+    cartridge: Rc<RefCell<dyn Cartridge>>,
 }
 
 impl PPU {
-    pub fn new(chr: Vec<u8>) -> Self {
+    pub fn new(cartridge: Rc<RefCell<dyn Cartridge>>) -> Self {
         PPU {
             control: Control::new(0b0000_0000),
             mask: Mask::new(0b0000_0000),
             status: Status::new(0b1010_0000),
             oamaddr: OAMAddress::new(0b0000_0000),
-            oamdata: 0b0000_0000,
-            ppuscroll: 0b0000_0000,
             address: Address::new(0x0000),
+            temp_address: Address::new(0x0000),
+            fine_x: 0,
             data: Data::new(0b0000_0000),
             frame: Frame::new(),
             vram: [0; 2048],
             oam_ram: [0; 256],
             palette_ram: [0; 32],
+            io_latch: 0,
+            io_latch_age: [0; 8],
             w: WriteToggle::FirstWrite,
             cycles: 0,
             scanline: 0,
+            region: Region::Ntsc,
+            palette: Palette::new(),
+            frame_count: 0,
             nmi_triggered: false,
-            chr: chr,
+            frame_complete: false,
+            is_sprite_limit_enabled: true,
+            secondary_oam: Vec::with_capacity(8),
+            is_sprite_zero_on_line: false,
+            next_tile_index: 0,
+            next_tile_attribute: 0,
+            next_tile_low: 0,
+            next_tile_high: 0,
+            pattern_shift_low: 0,
+            pattern_shift_high: 0,
+            attribute_shift_low: 0,
+            attribute_shift_high: 0,
+            cartridge: cartridge,
         }
     }
 
+    pub fn reset(&mut self) {
+        // https://www.nesdev.org/wiki/PPU_power_up_state, vram, oam and palettes are left untouched
+        self.control.write_u8(0b0000_0000);
+        self.mask.write_u8(0b0000_0000);
+        self.data.write_u8(0b0000_0000);
+        self.w = WriteToggle::FirstWrite;
+        self.cycles = 0;
+        self.scanline = 0;
+        self.frame_count = 0;
+        self.nmi_triggered = false;
+        self.frame_complete = false;
+    }
+
     pub fn write_control(&mut self, data: u8) {
         let current_nmi_enable = self.control.nmi_enable();
         self.control.write_u8(data);
+        self.temp_address.value =
+            (self.temp_address.value & 0b1111_0011_1111_1111) | (((data & 0b11) as u16) << 10);
         let next_nmi_enable = self.control.nmi_enable();
         if !current_nmi_enable && next_nmi_enable && self.status.is_in_v_blank() {
             // println!("nmi triggered by writing to control register");
@@ -86,9 +139,41 @@ impl PPU {
         }
     }
 
+    // Every write to a PPU register fills the latch, reads of write-only registers return it
+    pub fn write_io_latch(&mut self, data: u8) {
+        self.refresh_io_latch(data, 0xFF);
+    }
+
+    pub fn read_io_latch(&self) -> u8 {
+        return self.io_latch;
+    }
+
+    // Bits set in mask are driven by the read, the others keep their previous value
+    fn refresh_io_latch(&mut self, data: u8, mask: u8) {
+        self.io_latch = (self.io_latch & !mask) | (data & mask);
+        for bit in 0..8 {
+            if mask >> bit & 1 == 1 {
+                self.io_latch_age[bit] = 0;
+            }
+        }
+    }
+
+    fn decay_io_latch(&mut self) {
+        for bit in 0..8 {
+            if self.io_latch_age[bit] < IO_LATCH_DECAY_FRAMES {
+                self.io_latch_age[bit] += 1;
+            } else {
+                self.io_latch = self.io_latch & !(1 << bit);
+            }
+        }
+    }
+
+    // Only the top 3 bits exist, the rest comes from the latch
     pub fn read_status(&mut self) -> u8 {
-        let result = self.status.read_u8();
+        self.refresh_io_latch(self.status.read_u8(), 0b1110_0000);
+        let result = self.io_latch;
         self.status.clear_v_blank();
+        self.w = WriteToggle::FirstWrite;
         return result;
     }
 
@@ -100,67 +185,128 @@ impl PPU {
         self.mask.write_u8(data)
     }
 
+    pub fn write_scroll(&mut self, data: u8) {
+        match self.w {
+            WriteToggle::FirstWrite => {
+                self.temp_address.value =
+                    (self.temp_address.value & 0b1111_1111_1110_0000) | (data >> 3) as u16;
+                self.fine_x = data & 0b111;
+            }
+            WriteToggle::SecondWrite => {
+                let coarse_y = ((data >> 3) as u16) << 5;
+                let fine_y = ((data & 0b111) as u16) << 12;
+                self.temp_address.value =
+                    (self.temp_address.value & 0b0000_1100_0001_1111) | coarse_y | fine_y;
+            }
+        }
+        self.w.toggle();
+    }
+
+    // The first write goes to the high byte of t, the second one to the low byte,
+    // after which t is copied to v
     pub fn write_address(&mut self, data: u8) {
         match self.w {
             WriteToggle::FirstWrite => {
-                self.address.write_u16((data as u16) << 8);
+                self.temp_address.write_u16(
+                    (self.temp_address.read_u16() & 0x00FF) | (((data & 0b0011_1111) as u16) << 8),
+                );
             }
             WriteToggle::SecondWrite => {
-                self.address
-                    .write_u16((self.address.read_u16() & 0xFF00) | (data as u16));
+                self.temp_address
+                    .write_u16((self.temp_address.read_u16() & 0xFF00) | (data as u16));
+                self.address.write_u16(self.temp_address.read_u16());
             }
         }
         self.w.toggle();
     }
 
     pub fn read_data(&mut self) -> u8 {
+        let address = self.address.read_u16() & 0x3FFF;
+        if address >= 0x3F00 {
+            // Palette reads skip the buffer, which gets the nametable byte "below" the palette instead.
+            // Palette entries are only 6 bits wide, the top 2 bits come from the latch.
+            let mut palette = self.mem_read_u8(address) & 0b0011_1111;
+            if self.mask.is_grayscale() {
+                palette = palette & 0x30;
+            }
+            self.data.write_u8(self.mem_read_u8(address - 0x1000));
+            self.refresh_io_latch(palette, 0b0011_1111);
+            self.increment_address();
+            return self.io_latch;
+        }
+
         let result = self.data.read_u8();
-        self.data
-            .write_u8(self.mem_read_u8(self.address.read_u16()));
+        self.data.write_u8(self.mem_read_u8(address));
+        self.refresh_io_latch(result, 0xFF);
         self.increment_address();
         return result;
     }
 
     fn mem_read_u8(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x1FFF => self.chr[address as usize],
-            // todo: get nametable mirroring from cartrige, for now we assume it's vertical
-            0x2000..=0x2FFF => self.vram[(address & 0b10011111111111 - 0x2000) as usize],
-            0x3000..=0x3EFF => panic!("Can't access address {}", address),
-            0x3F00..=0x3FFF => {
-                let mirror_down_address = address & 0b11111100011111;
-                self.palette_ram[(mirror_down_address - 0x3f00) as usize]
-            }
+            0x0000..=0x1FFF => self.cartridge.borrow().ppu_read(address),
+            0x2000..=0x3EFF => self.cartridge.borrow().nametable_read(address, &self.vram),
+            0x3F00..=0x3FFF => self.palette_ram[palette_ram_index(address)],
             _ => panic!("Address {} is out of bounds", address),
         }
     }
 
     pub fn write_data(&mut self, data: u8) {
         // println!("Write {} to address {:0x}", data, self.address.read_u16());
-        self.mem_write_u8(self.address.read_u16(), data);
+        self.mem_write_u8(self.address.read_u16() & 0x3FFF, data);
         self.increment_address();
     }
 
+    pub fn read_oam_data(&mut self) -> u8 {
+        let address = self.oamaddr.read_u8();
+        let mut data = self.oam_ram[address as usize];
+        // bits 2-4 of the attribute byte don't exist and read back as 0
+        if address % 4 == 2 {
+            data = data & 0b1110_0011;
+        }
+        self.refresh_io_latch(data, 0xFF);
+        return data;
+    }
+
+    pub fn write_oam_data(&mut self, data: u8) {
+        if self.is_rendering() {
+            self.oamaddr.glitchy_increment();
+            return;
+        }
+        self.oam_ram[self.oamaddr.read_u8() as usize] = data;
+        self.oamaddr.increment();
+    }
+
+    // DMA writes through OAMDATA, so the copy starts at OAMADDR and wraps around
     pub fn dma_write(&mut self, data: &[u8]) {
         assert!(data.len() == 256);
-        self.oam_ram.clone_from_slice(data);
+        for byte in data {
+            self.oam_ram[self.oamaddr.read_u8() as usize] = *byte;
+            self.oamaddr.increment();
+        }
     }
 
     fn mem_write_u8(&mut self, address: u16, data: u8) {
         match address {
-            0x000..=0x1FFF => panic!("CHR Rom is read-only."),
-            // todo: get nametable mirroring from cartrige, for now we assume it's vertical
-            0x2000..=0x2FFF => self.vram[(address & 0b10011111111111 - 0x2000) as usize] = data,
-            0x3000..=0x3EFF => panic!("Can't access address {}", address),
-            0x3F00..=0x3FFF => {
-                let mirror_down_address = address & 0b11111100011111;
-                self.palette_ram[(mirror_down_address - 0x3f00) as usize] = data;
+            0x000..=0x1FFF => self.cartridge.borrow_mut().ppu_write(address, data),
+            0x2000..=0x3EFF => {
+                self.cartridge
+                    .borrow_mut()
+                    .nametable_write(address, data, &mut self.vram)
             }
+            0x3F00..=0x3FFF => self.palette_ram[palette_ram_index(address)] = data,
             _ => panic!("Address {} is out of bounds", address),
         }
     }
 
     fn increment_address(&mut self) {
+        // During rendering $2007 accesses bump v the way the renderer does, coarse x and y at once
+        if self.is_rendering() {
+            self.address.increment_coarse_x();
+            self.address.increment_y();
+            return;
+        }
+
         self.address.write_u16(
             self.address
                 .read_u16()
@@ -171,163 +317,393 @@ impl PPU {
     pub fn tick(&mut self) {
         // println!("Control {:08b}",. self.control.read_u8());
         // println!("cycle: {}, scanline: {}", self.cycles, self.scanline);
+        if self.is_rendering() {
+            self.fetch_background();
+            self.notify_sprite_fetch();
+            self.update_scroll();
+        }
         match self.cycles {
             0 => (),
             1..=256 => {
                 if self.scanline < 240 {
                     self.render_pixel(self.cycles - 1, self.scanline);
                 }
-            }
-            257..=340 => (),
-            _ => {
-                self.cycles = self.cycles - 341;
-                self.scanline += 1;
-
-                // todo: Implement sprite rendering with proper timing
-                if self.scanline == 240 {
-                    self.render_sprites();
-                }
-
-                if self.scanline == 241 {
+                if self.scanline == self.region.vblank_scanline() && self.cycles == 1 {
                     self.status.set_v_blank();
-                    self.scanline += 1;
+                    self.frame_complete = true;
+                    self.decay_io_latch();
                     if self.control.nmi_enable() {
-                        // println!("nmi triggered, from scanline 241");
                         self.nmi_triggered = true;
                     }
                 }
-
-                if self.scanline == 261 {
-                    {
-                        self.scanline = 0;
-                    }
+                if self.scanline == self.region.pre_render_scanline() && self.cycles == 1 {
+                    self.status.clear_flags();
+                }
+            }
+            _ => {
+                // OAMADDR is cleared while the sprite tiles are fetched
+                if self.cycles <= 320 && self.is_rendering() {
+                    self.oamaddr.write_u8(0);
+                }
+                if self.cycles == 257 {
+                    self.evaluate_sprites();
+                }
+                if self.cycles == 257 && self.scanline < 240 && self.mask.is_rendering_enabled() {
+                    self.evaluate_sprite_overflow();
                 }
             }
         }
+        self.next_dot();
+    }
+
+    fn next_dot(&mut self) {
+        // the last dot of the pre-render line is skipped on odd frames while rendering
+        let is_odd_frame = self.frame_count % 2 == 1;
+        let is_skipped_dot = self.scanline == self.region.pre_render_scanline()
+            && self.cycles == DOTS_PER_SCANLINE - 2
+            && is_odd_frame
+            && self.mask.is_rendering_enabled()
+            && self.region.has_odd_frame_skip();
+
         self.cycles += 1;
+        if self.cycles < DOTS_PER_SCANLINE && !is_skipped_dot {
+            return;
+        }
+
+        self.cycles = 0;
+        self.scanline += 1;
+        if self.scanline > self.region.pre_render_scanline() {
+            self.scanline = 0;
+            self.frame_count += 1;
+        }
+    }
+
+    fn is_rendering(&self) -> bool {
+        let is_rendering_line =
+            self.scanline < 240 || self.scanline == self.region.pre_render_scanline();
+        return is_rendering_line && self.mask.is_rendering_enabled();
+    }
+
+    // https://www.nesdev.org/wiki/PPU_rendering#Cycles_1-256
+    // Every 8 dots the nametable byte, attribute byte and both pattern planes of a tile are fetched.
+    // Dots 321-336 prefetch the first two tiles of the next line.
+    fn fetch_background(&mut self) {
+        if let 2..=257 | 322..=337 = self.cycles {
+            self.shift_background();
+        }
+
+        match self.cycles {
+            1..=256 | 321..=336 => match (self.cycles - 1) % 8 {
+                0 => {
+                    self.load_background_shifters();
+                    self.next_tile_index = self.mem_read_u8(self.address.tile_address());
+                }
+                2 => {
+                    let attribute_byte = self.mem_read_u8(self.address.attribute_address());
+                    // every attribute byte covers 4x4 tiles, split in 2x2 tile quadrants
+                    let shift = (self.address.coarse_y() & 2) << 1 | (self.address.coarse_x() & 2);
+                    self.next_tile_attribute = attribute_byte >> shift & 0b11;
+                }
+                4 => self.next_tile_low = self.fetch_pattern(self.background_tile_address()),
+                6 => self.next_tile_high = self.fetch_pattern(self.background_tile_address() + 8),
+                _ => (),
+            },
+            257 => self.load_background_shifters(),
+            // the last two nametable fetches of a line are never used
+            337 => {
+                self.load_background_shifters();
+                self.mem_read_u8(self.address.tile_address());
+            }
+            339 => {
+                self.mem_read_u8(self.address.tile_address());
+            }
+            _ => (),
+        }
+    }
+
+    fn background_tile_address(&self) -> u16 {
+        return self.control.background_pattern_table_address()
+            + self.next_tile_index as u16 * 16
+            + self.address.fine_y();
+    }
+
+    // the cartridge watches pattern fetches, the MMC3 counts scanlines with them
+    fn fetch_pattern(&mut self, address: u16) -> u8 {
+        self.cartridge.borrow_mut().notify_ppu_fetch(address);
+        return self.mem_read_u8(address);
+    }
+
+    fn load_background_shifters(&mut self) {
+        let expand = |bit: u8| if bit & 1 == 1 { 0xFF } else { 0x00 };
+        self.pattern_shift_low = (self.pattern_shift_low & 0xFF00) | self.next_tile_low as u16;
+        self.pattern_shift_high = (self.pattern_shift_high & 0xFF00) | self.next_tile_high as u16;
+        self.attribute_shift_low =
+            (self.attribute_shift_low & 0xFF00) | expand(self.next_tile_attribute);
+        self.attribute_shift_high =
+            (self.attribute_shift_high & 0xFF00) | expand(self.next_tile_attribute >> 1);
+    }
+
+    fn shift_background(&mut self) {
+        self.pattern_shift_low = self.pattern_shift_low << 1;
+        self.pattern_shift_high = self.pattern_shift_high << 1;
+        self.attribute_shift_low = self.attribute_shift_low << 1;
+        self.attribute_shift_high = self.attribute_shift_high << 1;
+    }
+
+    // Sprite patterns are looked up while drawing, the cartridge is still told where the
+    // fetches of each 8 dot slot on dots 257-320 would go.
+    fn notify_sprite_fetch(&mut self) {
+        if let 257..=320 = self.cycles {
+            if self.cycles % 8 == 5 {
+                let address = self.sprite_fetch_address((self.cycles as usize - 257) / 8);
+                self.cartridge.borrow_mut().notify_ppu_fetch(address);
+            }
+        }
+    }
+
+    // Pattern table of the sprite fetched in the given slot. 8x16 sprites choose their own
+    // table, and empty slots fetch tile $FF.
+    fn sprite_fetch_address(&self, slot: usize) -> u16 {
+        if self.control.sprite_height() == 8 {
+            return self.control.sprite_pattern_table_address();
+        }
+        let tile_index = match self.secondary_oam.get(slot) {
+            Some(sprite) => sprite[1],
+            None => 0xFF,
+        };
+        return (tile_index as u16 & 1) * 0x1000;
+    }
+
+    // https://www.nesdev.org/wiki/PPU_scrolling#Wrapping_around
+    fn update_scroll(&mut self) {
+        match self.cycles {
+            1..=256 | 321..=336 => {
+                if self.cycles.is_multiple_of(8) {
+                    self.address.increment_coarse_x();
+                }
+                if self.cycles == 256 {
+                    self.address.increment_y();
+                }
+            }
+            257 => self.address.copy_horizontal(&self.temp_address),
+            280..=304 if self.scanline == self.region.pre_render_scanline() => {
+                self.address.copy_vertical(&self.temp_address)
+            }
+            _ => (),
+        }
     }
 
     fn render_pixel(&mut self, x: u16, y: u16) {
-        let bank = (self.control.background_pattern_table_address() as u16) * 0x1000;
-        // println!("bank: {}", bank);
-        assert!(bank == 0 || bank == 0x1000);
-        let nametable_x = x / 8;
-        let nametable_y = y / 8;
-        let nametable_base = self.control.nametable_base();
-        let nametable_index = nametable_base + nametable_x + nametable_y * 32;
-        assert!(nametable_index - nametable_base < 0x400);
-        let nametable_byte = self.mem_read_u8(nametable_index) as u16;
-
-        let tile: &[u8] = &self.chr
-            [(bank + nametable_byte * 16) as usize..=(bank + nametable_byte * 16 + 15) as usize];
-
-        let shift = vec![7, 6, 5, 4, 3, 2, 1, 0];
-
-        let upper = tile[(y % 8) as usize] >> (shift[(x % 8) as usize]);
-        let lower = tile[((y % 8) + 8) as usize] >> (shift[(x % 8) as usize]);
-
-        let palette_index = (1 & lower) << 1 | (1 & upper);
-        let background_palette = self.background_palette(x, y);
-        let rgb = SYSTEM_PALETTE[background_palette[palette_index as usize] as usize];
-
-        self.frame.set_pixel(x as usize, y as usize, rgb);
-    }
-
-    fn background_palette(&self, x: u16, y: u16) -> [u8; 4] {
-        const ATTRIBUTE_TABLE_OFFSET: u16 = 0x3C0;
-        let nametable_base = self.control.nametable_base();
-        let block_x = x / 32;
-        let block_y = y / 32;
-        assert!(block_x < 8);
-        assert!(block_y < 8);
-        let attribute_table_index = block_y * 8 + block_x;
-        assert!(attribute_table_index < 64);
-        let ram_addr = nametable_base + ATTRIBUTE_TABLE_OFFSET + attribute_table_index;
-
-        let attribute_table_byte: u8 = self.mem_read_u8(ram_addr);
-
-        let quadrant: (u16, u16) = ((x % 32) / 16, (y % 32) / 16);
-
-        let palette_base = match quadrant {
-            (0, 0) => attribute_table_byte & 0b11,
-            (1, 0) => attribute_table_byte >> 2 & 0b11,
-            (0, 1) => attribute_table_byte >> 4 & 0b11,
-            (1, 1) => attribute_table_byte >> 6 & 0b11,
-            _ => panic!("Impossible!"),
+        if !self.mask.is_rendering_enabled() {
+            // the backdrop color is shown, unless v points into palette RAM
+            // https://www.nesdev.org/wiki/PPU_palettes#The_background_palette_hack
+            let v = self.address.read_u16() & 0x3FFF;
+            let palette_address = if v >= 0x3F00 { v } else { 0x3F00 };
+            let pixel = self.output_pixel(self.mem_read_u8(palette_address));
+            self.frame
+                .set_pixel(x as usize, y as usize, pixel, &self.palette);
+            return;
+        }
+
+        // fine x selects the bit of the shift registers that is output
+        let bit = 0x8000 >> self.fine_x;
+        let shifter_bit = |shifter: u16| (shifter & bit != 0) as u8;
+
+        let is_background_visible =
+            self.mask.show_background() && (x >= 8 || self.mask.show_background_left());
+        let palette_index = match is_background_visible {
+            true => shifter_bit(self.pattern_shift_high) << 1 | shifter_bit(self.pattern_shift_low),
+            false => 0,
+        };
+        let palette =
+            shifter_bit(self.attribute_shift_high) << 1 | shifter_bit(self.attribute_shift_low);
+        // transparent pixels show the universal background color at $3F00
+        let mut color = match palette_index {
+            0 => self.palette_ram[0],
+            _ => self.palette_ram[(palette * 4 + palette_index) as usize],
         };
 
-        let palette_start = (palette_base as usize) * 4;
+        // The first opaque sprite in OAM order wins, even when it is behind the background
+        let are_sprites_visible =
+            self.mask.show_sprites() && (x >= 8 || self.mask.show_sprites_left());
+        let sprite = self
+            .secondary_oam
+            .iter()
+            .enumerate()
+            .filter(|_| are_sprites_visible)
+            .map(|(i, sprite)| (i, *sprite, self.sprite_pixel(sprite, x, y)))
+            .find(|(_, _, sprite_palette_index)| *sprite_palette_index != 0);
 
-        let result = [
-            self.palette_ram[palette_start],
-            self.palette_ram[(palette_start + 1) as usize],
-            self.palette_ram[(palette_start + 2) as usize],
-            self.palette_ram[(palette_start + 3) as usize],
-        ];
+        if let Some((i, sprite, sprite_palette_index)) = sprite {
+            let is_sprite_zero = i == 0 && self.is_sprite_zero_on_line;
+            if is_sprite_zero && palette_index != 0 && self.is_sprite_zero_hit_possible(x) {
+                self.status.set_sprite_zero_hit();
+            }
 
-        return result;
+            let attributes = sprite[2];
+            let is_behind_background = attributes >> 5 & 1 == 1;
+            if palette_index == 0 || !is_behind_background {
+                let sprite_palette = self.sprite_palette(attributes & 0b11);
+                color = sprite_palette[sprite_palette_index as usize];
+            }
+        }
+
+        let pixel = self.output_pixel(color);
+        self.frame
+            .set_pixel(x as usize, y as usize, pixel, &self.palette);
     }
 
-    fn render_sprites(&mut self) {
-        for i in (0..self.oam_ram.len()).step_by(4).rev() {
-            let tile_idx = self.oam_ram[i + 1] as u16;
-            let tile_x = self.oam_ram[i + 3] as usize;
-            let tile_y = self.oam_ram[i] as usize;
+    // https://www.nesdev.org/wiki/PPU_palettes#Color_de-emphasis
+    // The emphasis bits are stored above the palette index, in red, green, blue order
+    fn output_pixel(&self, color: u8) -> u16 {
+        let color = match self.mask.is_grayscale() {
+            true => color & 0x30,
+            false => color & 0x3F,
+        };
 
-            let flip_vertical = if self.oam_ram[i + 2] >> 7 & 1 == 1 {
-                true
-            } else {
-                false
-            };
-            let flip_horizontal = if self.oam_ram[i + 2] >> 6 & 1 == 1 {
-                true
-            } else {
-                false
-            };
-            let palette_ram_index = self.oam_ram[i + 2] & 0b11;
-            let sprite_palette = self.sprite_palette(palette_ram_index);
-
-            let bank: u16 = self.control.sprite_pattern_table_address();
-            assert!(bank == 0 || bank == 0x1000);
-            let tile =
-                &self.chr[(bank + tile_idx * 16) as usize..=(bank + tile_idx * 16 + 15) as usize];
-
-            for y in 0..=7 {
-                let mut upper = tile[y];
-                let mut lower = tile[y + 8];
-                'ololo: for x in (0..=7).rev() {
-                    let value = (1 & lower) << 1 | (1 & upper);
-                    upper = upper >> 1;
-                    lower = lower >> 1;
-                    let rgb = match value {
-                        0 => continue 'ololo, // skip coloring the pixel
-                        1 => SYSTEM_PALETTE[sprite_palette[1] as usize],
-                        2 => SYSTEM_PALETTE[sprite_palette[2] as usize],
-                        3 => SYSTEM_PALETTE[sprite_palette[3] as usize],
-                        _ => panic!("can't be"),
-                    };
-                    match (flip_horizontal, flip_vertical) {
-                        (false, false) => self.frame.set_pixel(tile_x + x, tile_y + y, rgb),
-                        (true, false) => self.frame.set_pixel(tile_x + 7 - x, tile_y + y, rgb),
-                        (false, true) => self.frame.set_pixel(tile_x + x, tile_y + 7 - y, rgb),
-                        (true, true) => self.frame.set_pixel(tile_x + 7 - x, tile_y + 7 - y, rgb),
-                    }
-                }
+        let (mut emphasize_red, mut emphasize_green, emphasize_blue) = (
+            self.mask.emphasize_red(),
+            self.mask.emphasize_green(),
+            self.mask.emphasize_blue(),
+        );
+        if self.region.swaps_red_green_emphasis() {
+            (emphasize_red, emphasize_green) = (emphasize_green, emphasize_red);
+        }
+        let emphasis =
+            emphasize_red as u16 | (emphasize_green as u16) << 1 | (emphasize_blue as u16) << 2;
+        return color as u16 | emphasis << 6;
+    }
+
+    fn is_sprite_zero_hit_possible(&self, x: u16) -> bool {
+        let is_clipped =
+            x < 8 && (!self.mask.show_background_left() || !self.mask.show_sprites_left());
+        return self.mask.show_background() && self.mask.show_sprites() && x != 255 && !is_clipped;
+    }
+
+    // https://www.nesdev.org/wiki/PPU_sprite_evaluation
+    // Sprites in range of the current line are copied to secondary OAM and drawn on the next one.
+    fn evaluate_sprites(&mut self) {
+        self.secondary_oam.clear();
+        self.is_sprite_zero_on_line = false;
+        if self.scanline >= 240 || !self.mask.is_rendering_enabled() {
+            return;
+        }
+
+        let sprite_height = self.control.sprite_height();
+        for sprite in 0..64 {
+            let oam_index = sprite * 4;
+            let sprite_y = self.oam_ram[oam_index] as u16;
+            if self.scanline < sprite_y || self.scanline >= sprite_y + sprite_height {
+                continue;
+            }
+            if self.secondary_oam.len() == 8 && self.is_sprite_limit_enabled {
+                break;
+            }
+
+            if sprite == 0 {
+                self.is_sprite_zero_on_line = true;
+            }
+            let mut entry = [0; 4];
+            entry.copy_from_slice(&self.oam_ram[oam_index..oam_index + 4]);
+            self.secondary_oam.push(entry);
+        }
+    }
+
+    // Palette index of the sprite at a screen position, 0 when it is transparent or doesn't cover it
+    fn sprite_pixel(&self, sprite: &[u8; 4], x: u16, y: u16) -> u8 {
+        // sprites are drawn one line below their y coordinate
+        let sprite_y = sprite[0] as u16 + 1;
+        let sprite_x = sprite[3] as u16;
+        let sprite_height = self.control.sprite_height();
+        if y < sprite_y || y >= sprite_y + sprite_height || x < sprite_x || x >= sprite_x + 8 {
+            return 0;
+        }
+
+        let attributes = sprite[2];
+        let mut row = y - sprite_y;
+        let mut column = x - sprite_x;
+        // vertical flipping swaps the two halves of 8x16 sprites too
+        if attributes >> 7 & 1 == 1 {
+            row = sprite_height - 1 - row;
+        }
+        if attributes >> 6 & 1 == 1 {
+            column = 7 - column;
+        }
+
+        // 8x16 sprites take their pattern table from bit 0 of the tile index,
+        // the top half uses the even tile and the bottom half the following one
+        let (bank, tile_index) = match sprite_height {
+            8 => (
+                self.control.sprite_pattern_table_address(),
+                sprite[1] as u16,
+            ),
+            _ => (
+                (sprite[1] as u16 & 1) * 0x1000,
+                (sprite[1] as u16 & 0xFE) + row / 8,
+            ),
+        };
+        let tile_address = bank + tile_index * 16 + row % 8;
+        let upper = self.mem_read_u8(tile_address) >> (7 - column);
+        let lower = self.mem_read_u8(tile_address + 8) >> (7 - column);
+        return (1 & lower) << 1 | (1 & upper);
+    }
+
+    // https://www.nesdev.org/wiki/PPU_sprite_evaluation#Sprite_overflow_bug
+    fn evaluate_sprite_overflow(&mut self) {
+        let scanline = self.scanline;
+        let sprite_height = self.control.sprite_height();
+        let is_in_range = |y: u8| scanline >= y as u16 && scanline < y as u16 + sprite_height;
+
+        let mut sprite = 0;
+        let mut found = 0;
+        while sprite < 64 && found < 8 {
+            if is_in_range(self.oam_ram[sprite * 4]) {
+                found += 1;
+            }
+            sprite += 1;
+        }
+
+        // After finding 8 sprites the PPU increments the byte offset together with the
+        // sprite index, so tile numbers, attributes and x positions get compared as y coordinates
+        let mut offset = 0;
+        while found == 8 && sprite < 64 {
+            if is_in_range(self.oam_ram[sprite * 4 + offset]) {
+                self.status.set_sprite_overflow();
+                return;
             }
+            sprite += 1;
+            offset = (offset + 1) % 4;
         }
     }
 
-    fn sprite_palette(&self, palette_ram_index: u8) -> [u8; 4] {
-        let start = 0x11 + (palette_ram_index * 4) as usize;
+    fn sprite_palette(&self, palette: u8) -> [u8; 4] {
+        let start = 0x10 + (palette * 4) as usize;
+        // entry 0 is transparent, render_pixel never looks it up
         return [
             0,
-            self.palette_ram[start],
             self.palette_ram[start + 1],
             self.palette_ram[start + 2],
+            self.palette_ram[start + 3],
         ];
     }
 }
 
+// https://www.nesdev.org/wiki/PPU_palettes#Memory_Map
+// $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
+fn palette_ram_index(address: u16) -> usize {
+    let index = (address & 0x1F) as usize;
+    if index >= 0x10 && index.is_multiple_of(4) {
+        return index - 0x10;
+    }
+    return index;
+}
+
+#[cfg(test)]
+fn test_cartridge() -> Rc<RefCell<dyn Cartridge>> {
+    use crate::rom::{Mirroring, Rom};
+    crate::cartridge::new(Rom::new(vec![0; 0x4000], vec![], 0, Mirroring::Vertical))
+}
+
 #[cfg(test)]
 mod test_ppu {
     use super::*;
@@ -336,33 +712,493 @@ mod test_ppu {
     #[test]
     fn test_power_up_state() {
         // Test Power-up state as documented in https://www.nesdev.org/wiki/PPU_power_up_state
-        let ppu = PPU::new(vec![]);
+        let ppu = PPU::new(test_cartridge());
         assert_eq!(0b0000_0000, ppu.control.read_u8());
         assert_eq!(0b0000_0000, ppu.mask.read_u8());
         assert_eq!(0b1010_0000, ppu.status.read_u8());
-        assert_eq!(0b0000_0000, ppu.ppuscroll);
         assert_eq!(0b0000_0000, ppu.oamaddr.read_u8());
         assert_eq!(0x0000, ppu.address.read_u16());
+        assert_eq!(0x0000, ppu.temp_address.read_u16());
+        assert_eq!(0, ppu.fine_x);
         assert_eq!(0b0000_0000, ppu.data.read_u8());
         assert_eq!([0; 2048], ppu.vram)
     }
+
+    #[test]
+    fn test_nametables_are_read_through_the_cartridge() {
+        let mut ppu = PPU::new(test_cartridge());
+        ppu.write_address(0x30);
+        ppu.write_address(0x05);
+        ppu.write_data(0xAB);
+        assert_eq!(0xAB, ppu.vram[0x005]);
+
+        ppu.write_address(0x20);
+        ppu.write_address(0x05);
+        ppu.read_data();
+        assert_eq!(0xAB, ppu.read_data());
+    }
 }
 
 #[test]
 fn test_address_register_first_write() {
-    let mut ppu = PPU::new(vec![]);
+    let mut ppu = PPU::new(test_cartridge());
 
     ppu.write_address(0xAA);
-    assert_eq!(0xAA00, ppu.address.read_u16());
+    // the first write only reaches t, and bit 14 is cleared
+    assert_eq!(0x2A00, ppu.temp_address.read_u16());
+    assert_eq!(0x0000, ppu.address.read_u16());
     assert_eq!(WriteToggle::SecondWrite, ppu.w);
 }
 
 #[test]
 fn test_address_register_second_write() {
-    let mut ppu = PPU::new(vec![]);
+    let mut ppu = PPU::new(test_cartridge());
     ppu.w = WriteToggle::SecondWrite;
 
     ppu.write_address(0xAA);
     assert_eq!(0x00AA, ppu.address.read_u16());
     assert_eq!(WriteToggle::FirstWrite, ppu.w);
 }
+
+// Example from https://www.nesdev.org/wiki/PPU_scrolling#Summary
+#[test]
+fn test_scroll_and_address_writes_share_t() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.write_control(0b0000_0000);
+    ppu.read_status();
+    ppu.write_scroll(0x7D);
+    assert_eq!(0b0000_0000_0000_1111, ppu.temp_address.read_u16());
+    assert_eq!(0b101, ppu.fine_x);
+    ppu.write_scroll(0x5E);
+    assert_eq!(0b0110_0001_0110_1111, ppu.temp_address.read_u16());
+    ppu.write_address(0x3D);
+    assert_eq!(0b0011_1101_0110_1111, ppu.temp_address.read_u16());
+    ppu.write_address(0xF0);
+    assert_eq!(0b0011_1101_1111_0000, ppu.temp_address.read_u16());
+    assert_eq!(0b0011_1101_1111_0000, ppu.address.read_u16());
+}
+
+#[test]
+fn test_control_write_selects_nametable_in_t() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.write_control(0b0000_0011);
+    assert_eq!(0b0000_1100_0000_0000, ppu.temp_address.read_u16());
+}
+
+#[test]
+fn test_pre_render_line_reloads_v_from_t() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.write_mask(0b0000_1000);
+    ppu.write_control(0b0000_0001);
+    ppu.write_scroll(0x0D);
+    ppu.write_scroll(0x12);
+    ppu.scanline = ppu.region.pre_render_scanline();
+    ppu.cycles = 1;
+    while ppu.scanline != 0 {
+        ppu.tick();
+    }
+    // the first two tiles of the line were already fetched at the end of the pre-render line
+    assert_eq!(0b0010_0100_0100_0011, ppu.address.read_u16());
+
+    // fine x doesn't change when the next tile is fetched
+    tick_until(&mut ppu, 0, 9);
+    assert_eq!(4, ppu.address.coarse_x());
+}
+
+// Tile 1 is fully opaque and covers the first nametable, every sprite is hidden below the screen
+#[cfg(test)]
+fn test_sprite_ppu() -> PPU {
+    let mut ppu = PPU::new(test_cartridge());
+    for row in 0..8 {
+        ppu.mem_write_u8(0x0010 + row, 0xFF);
+    }
+    for tile in 0..0x3C0 {
+        ppu.mem_write_u8(0x2000 + tile, 1);
+    }
+    ppu.dma_write(&[0xFF; 256]);
+    ppu.write_mask(0b0001_1000);
+    ppu.status.write_u8(0);
+    return ppu;
+}
+
+#[cfg(test)]
+fn tick_until(ppu: &mut PPU, scanline: u16, cycles: u16) {
+    while ppu.scanline != scanline || ppu.cycles != cycles {
+        ppu.tick();
+    }
+}
+
+#[test]
+fn test_sprite_zero_hit() {
+    let mut ppu = test_sprite_ppu();
+    ppu.oam_ram[0..4].copy_from_slice(&[10, 1, 0, 20]);
+
+    tick_until(&mut ppu, 11, 21);
+    assert_eq!(0, ppu.status.read_u8() & 0b0100_0000);
+    ppu.tick();
+    assert_eq!(0b0100_0000, ppu.status.read_u8() & 0b0100_0000);
+
+    let pre_render_scanline = ppu.region.pre_render_scanline();
+    tick_until(&mut ppu, pre_render_scanline, 2);
+    assert_eq!(0, ppu.status.read_u8() & 0b0100_0000);
+}
+
+#[test]
+fn test_sprite_zero_hit_needs_opaque_background() {
+    let mut ppu = test_sprite_ppu();
+    ppu.oam_ram[0..4].copy_from_slice(&[10, 1, 0, 20]);
+    for tile in 0..0x3C0 {
+        ppu.mem_write_u8(0x2000 + tile, 0);
+    }
+    tick_until(&mut ppu, 240, 1);
+    assert_eq!(0, ppu.status.read_u8() & 0b0100_0000);
+}
+
+#[test]
+fn test_sprite_overflow() {
+    let mut ppu = test_sprite_ppu();
+    for sprite in 0..9 {
+        ppu.oam_ram[sprite * 4] = 10;
+    }
+    tick_until(&mut ppu, 10, 257);
+    assert_eq!(0, ppu.status.read_u8() & 0b0010_0000);
+    ppu.tick();
+    assert_eq!(0b0010_0000, ppu.status.read_u8() & 0b0010_0000);
+}
+
+#[test]
+fn test_sprite_overflow_bug_reads_tile_index_as_y() {
+    let mut ppu = test_sprite_ppu();
+    for sprite in 0..8 {
+        ppu.oam_ram[sprite * 4] = 10;
+    }
+    // sprite 8 is out of range, for sprite 9 the tile index is compared instead of y
+    ppu.oam_ram[9 * 4 + 1] = 10;
+    tick_until(&mut ppu, 11, 1);
+    assert_eq!(0b0010_0000, ppu.status.read_u8() & 0b0010_0000);
+}
+
+#[cfg(test)]
+fn pixel(ppu: &PPU, x: usize, y: usize) -> (u8, u8, u8) {
+    let base = (y * 256 + x) * 3;
+    let bytes = &ppu.frame.bytes;
+    return (bytes[base], bytes[base + 1], bytes[base + 2]);
+}
+
+#[test]
+fn test_sprite_is_drawn_one_line_below_its_y() {
+    let mut ppu = test_sprite_ppu();
+    for tile in 0..0x3C0 {
+        ppu.mem_write_u8(0x2000 + tile, 0);
+    }
+    ppu.palette_ram[0x11] = 0x16;
+    ppu.oam_ram[0..4].copy_from_slice(&[10, 1, 0, 20]);
+    tick_until(&mut ppu, 240, 1);
+    assert_eq!(SYSTEM_PALETTE[0x00], pixel(&ppu, 20, 10));
+    assert_eq!(SYSTEM_PALETTE[0x16], pixel(&ppu, 20, 11));
+    assert_eq!(SYSTEM_PALETTE[0x16], pixel(&ppu, 27, 18));
+    assert_eq!(SYSTEM_PALETTE[0x00], pixel(&ppu, 20, 19));
+}
+
+#[test]
+fn test_sprite_behind_background_priority() {
+    let mut ppu = test_sprite_ppu();
+    ppu.palette_ram[0x01] = 0x01;
+    ppu.palette_ram[0x11] = 0x16;
+    ppu.oam_ram[0..4].copy_from_slice(&[10, 1, 0b0010_0000, 20]);
+    ppu.oam_ram[4..8].copy_from_slice(&[10, 1, 0b0000_0000, 40]);
+    tick_until(&mut ppu, 240, 1);
+    assert_eq!(SYSTEM_PALETTE[0x01], pixel(&ppu, 20, 11));
+    assert_eq!(SYSTEM_PALETTE[0x16], pixel(&ppu, 40, 11));
+}
+
+#[test]
+fn test_sprite_limit() {
+    let mut ppu = test_sprite_ppu();
+    ppu.palette_ram[0x11] = 0x16;
+    for sprite in 0..9 {
+        ppu.oam_ram[sprite * 4..sprite * 4 + 4].copy_from_slice(&[10, 1, 0, sprite as u8 * 8]);
+    }
+    tick_until(&mut ppu, 240, 1);
+    assert_eq!(SYSTEM_PALETTE[0x16], pixel(&ppu, 56, 11));
+    assert_ne!(SYSTEM_PALETTE[0x16], pixel(&ppu, 64, 11));
+
+    ppu.is_sprite_limit_enabled = false;
+    ppu.tick();
+    tick_until(&mut ppu, 240, 1);
+    assert_eq!(SYSTEM_PALETTE[0x16], pixel(&ppu, 64, 11));
+}
+
+#[test]
+fn test_8x16_sprites() {
+    let mut ppu = test_sprite_ppu();
+    for tile in 0..0x3C0 {
+        ppu.mem_write_u8(0x2000 + tile, 0);
+    }
+    // tile $1 in the second pattern table has only its top row set, tile $2 has its bottom row set
+    ppu.mem_write_u8(0x1010, 0xFF);
+    ppu.mem_write_u8(0x1027, 0xFF);
+    ppu.palette_ram[0x11] = 0x16;
+    ppu.write_control(0b0010_0000);
+    // tile index $01 selects tiles $0 and $1 of the second pattern table
+    ppu.oam_ram[0..4].copy_from_slice(&[10, 0x01, 0, 20]);
+    // flipped vertically, so tile $3 is on top and tile $2 below it
+    ppu.oam_ram[4..8].copy_from_slice(&[10, 0x03, 0b1000_0000, 40]);
+    tick_until(&mut ppu, 240, 1);
+
+    assert_ne!(SYSTEM_PALETTE[0x16], pixel(&ppu, 20, 11));
+    assert_eq!(SYSTEM_PALETTE[0x16], pixel(&ppu, 20, 19));
+    assert_ne!(SYSTEM_PALETTE[0x16], pixel(&ppu, 20, 26));
+
+    assert_eq!(SYSTEM_PALETTE[0x16], pixel(&ppu, 40, 19));
+    assert_ne!(SYSTEM_PALETTE[0x16], pixel(&ppu, 40, 26));
+}
+
+#[test]
+fn test_rendering_disabled_shows_backdrop() {
+    let mut ppu = test_sprite_ppu();
+    ppu.write_mask(0b0000_0000);
+    ppu.palette_ram[0x00] = 0x21;
+    ppu.palette_ram[0x05] = 0x16;
+    tick_until(&mut ppu, 1, 1);
+    assert_eq!(SYSTEM_PALETTE[0x21], pixel(&ppu, 100, 0));
+
+    // v pointing at palette RAM shows that color instead
+    ppu.address.write_u16(0x3F05);
+    tick_until(&mut ppu, 2, 1);
+    assert_eq!(SYSTEM_PALETTE[0x16], pixel(&ppu, 100, 1));
+}
+
+#[test]
+fn test_background_left_column_clipping() {
+    let mut ppu = test_sprite_ppu();
+    ppu.palette_ram[0x01] = 0x01;
+    tick_until(&mut ppu, 2, 1);
+    assert_eq!(SYSTEM_PALETTE[0x00], pixel(&ppu, 7, 1));
+    assert_eq!(SYSTEM_PALETTE[0x01], pixel(&ppu, 8, 1));
+
+    ppu.write_mask(0b0000_1010);
+    tick_until(&mut ppu, 3, 1);
+    assert_eq!(SYSTEM_PALETTE[0x01], pixel(&ppu, 7, 2));
+}
+
+#[test]
+fn test_sprite_left_column_clipping() {
+    let mut ppu = test_sprite_ppu();
+    for tile in 0..0x3C0 {
+        ppu.mem_write_u8(0x2000 + tile, 0);
+    }
+    ppu.palette_ram[0x11] = 0x16;
+    ppu.oam_ram[0..4].copy_from_slice(&[10, 1, 0, 4]);
+    tick_until(&mut ppu, 12, 1);
+    assert_eq!(SYSTEM_PALETTE[0x00], pixel(&ppu, 7, 11));
+    assert_eq!(SYSTEM_PALETTE[0x16], pixel(&ppu, 8, 11));
+}
+
+#[test]
+fn test_grayscale_and_emphasis() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.write_mask(0b0000_0001);
+    assert_eq!(0x10, ppu.output_pixel(0x16));
+
+    ppu.write_mask(0b0010_0000);
+    let (red, green, blue) = SYSTEM_PALETTE[0x20];
+    let attenuated = |value: u8| (value as u32 * palette::EMPHASIS_ATTENUATION / 1000) as u8;
+    assert_eq!(
+        (red, attenuated(green), attenuated(blue)),
+        ppu.palette.pixel_color(ppu.output_pixel(0x20))
+    );
+}
+
+#[test]
+fn test_data_access_while_rendering_increments_coarse_x_and_y() {
+    let mut ppu = test_sprite_ppu();
+    ppu.scanline = 100;
+    ppu.address.write_u16(0b0000_0000_0110_0101);
+    ppu.write_data(0);
+    assert_eq!(0b0001_0000_0110_0110, ppu.address.read_u16());
+}
+
+#[test]
+fn test_oam_data_read_and_write() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.scanline = 241;
+    ppu.write_oam_address(0x10);
+    ppu.write_oam_data(0xAA);
+    ppu.write_oam_data(0xBB);
+    ppu.write_oam_data(0xFF);
+    assert_eq!(0x13, ppu.oamaddr.read_u8());
+    assert_eq!([0xAA, 0xBB, 0xFF], ppu.oam_ram[0x10..0x13]);
+
+    ppu.write_oam_address(0x10);
+    assert_eq!(0xAA, ppu.read_oam_data());
+    // reads don't increment OAMADDR
+    assert_eq!(0xAA, ppu.read_oam_data());
+    ppu.write_oam_address(0x12);
+    assert_eq!(0b1110_0011, ppu.read_oam_data());
+}
+
+#[test]
+fn test_oam_data_write_while_rendering() {
+    let mut ppu = test_sprite_ppu();
+    ppu.scanline = 100;
+    ppu.cycles = 100;
+    ppu.write_oam_address(0x11);
+    ppu.write_oam_data(0xAA);
+    assert_eq!(0xFF, ppu.oam_ram[0x11]);
+    assert_eq!(0x15, ppu.oamaddr.read_u8());
+}
+
+#[test]
+fn test_dma_starts_at_oam_address() {
+    let mut ppu = PPU::new(test_cartridge());
+    let data: Vec<u8> = (0..=255).collect();
+    ppu.write_oam_address(0x04);
+    ppu.dma_write(&data);
+    assert_eq!(0x00, ppu.oam_ram[0x04]);
+    assert_eq!(0xFC, ppu.oam_ram[0x00]);
+    assert_eq!(0x04, ppu.oamaddr.read_u8());
+}
+
+#[test]
+fn test_palette_reads_are_not_buffered() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.vram[0x701] = 0x12;
+    ppu.palette_ram[0x01] = 0x2C;
+    ppu.write_io_latch(0xC0);
+    ppu.write_address(0x3F);
+    ppu.write_address(0x01);
+    // the top 2 bits come from the latch
+    assert_eq!(0xEC, ppu.read_data());
+    // the buffer got the nametable byte at $2F01, which is mirrored to the second page
+    assert_eq!(0x12, ppu.data.read_u8());
+}
+
+#[test]
+fn test_status_low_bits_come_from_io_latch() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.status.write_u8(0b1000_0000);
+    ppu.write_io_latch(0b0101_0101);
+    assert_eq!(0b1001_0101, ppu.read_status());
+    assert_eq!(0b1001_0101, ppu.read_io_latch());
+}
+
+#[test]
+fn test_io_latch_decays() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.write_io_latch(0xFF);
+    for _ in 0..IO_LATCH_DECAY_FRAMES {
+        ppu.decay_io_latch();
+    }
+    assert_eq!(0xFF, ppu.read_io_latch());
+    // reading status refreshes only the top 3 bits
+    ppu.read_status();
+    ppu.decay_io_latch();
+    // power-up status is 0b1010_0000
+    assert_eq!(0b1010_0000, ppu.read_io_latch());
+}
+
+#[test]
+fn test_sprite_backdrop_entries_mirror_background_entries() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.write_address(0x3F);
+    ppu.write_address(0x10);
+    ppu.write_data(0x21);
+    assert_eq!(0x21, ppu.palette_ram[0x00]);
+
+    ppu.write_address(0x3F);
+    ppu.write_address(0x1C);
+    ppu.write_data(0x16);
+    assert_eq!(0x16, ppu.palette_ram[0x0C]);
+
+    // $3F11 is a real sprite entry, and $3F30 mirrors the whole palette RAM
+    ppu.write_address(0x3F);
+    ppu.write_address(0x11);
+    ppu.write_data(0x2A);
+    assert_eq!(0x2A, ppu.palette_ram[0x11]);
+    assert_eq!(0x21, ppu.mem_read_u8(0x3F30));
+    assert_eq!(0x16, ppu.mem_read_u8(0x3F3C));
+}
+
+#[test]
+fn test_transparent_background_shows_universal_background_color() {
+    let mut ppu = test_sprite_ppu();
+    for tile in 0..0x3C0 {
+        ppu.mem_write_u8(0x2000 + tile, 0);
+    }
+    ppu.palette_ram[0x00] = 0x21;
+    // the first entry of the other background palettes is never shown
+    ppu.palette_ram[0x04] = 0x16;
+    ppu.vram[0x3C0] = 0b01;
+    tick_until(&mut ppu, 10, 10);
+    assert_eq!(SYSTEM_PALETTE[0x21], pixel(&ppu, 8, 8));
+}
+
+#[test]
+fn test_background_is_drawn_from_fetched_tiles_with_fine_x() {
+    let mut ppu = test_sprite_ppu();
+    // only the first column of tile 1 is opaque
+    for row in 0..8 {
+        ppu.mem_write_u8(0x0010 + row, 0b1000_0000);
+    }
+    ppu.palette_ram[0x01] = 0x16;
+    ppu.write_mask(0b0000_1010);
+    ppu.write_scroll(3);
+    ppu.write_scroll(0);
+    ppu.scanline = ppu.region.pre_render_scanline();
+    ppu.cycles = 1;
+    tick_until(&mut ppu, 1, 1);
+    for x in 0..32 {
+        let expected = match x % 8 {
+            5 => SYSTEM_PALETTE[0x16],
+            _ => SYSTEM_PALETTE[0x00],
+        };
+        assert_eq!(expected, pixel(&ppu, x, 0));
+    }
+}
+
+#[test]
+fn test_v_blank_starts_on_second_dot_of_scanline_241() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.status.write_u8(0);
+    ppu.write_control(0b1000_0000);
+    tick_until(&mut ppu, 241, 1);
+    assert!(!ppu.nmi_triggered);
+    assert_eq!(0, ppu.status.read_u8() & 0b1000_0000);
+    ppu.tick();
+    assert!(ppu.nmi_triggered);
+    assert_eq!(0b1000_0000, ppu.status.read_u8() & 0b1000_0000);
+}
+
+#[test]
+fn test_enabling_nmi_during_v_blank_triggers_nmi() {
+    let mut ppu = PPU::new(test_cartridge());
+    ppu.status.write_u8(0);
+    ppu.write_control(0b0000_0000);
+    tick_until(&mut ppu, 241, 5);
+    assert!(!ppu.nmi_triggered);
+    ppu.write_control(0b1000_0000);
+    assert!(ppu.nmi_triggered);
+}
+
+#[test]
+fn test_odd_frames_skip_a_dot_while_rendering() {
+    let frame_length = |ppu: &mut PPU| {
+        let mut dots = 0;
+        let frame_count = ppu.frame_count;
+        while ppu.frame_count == frame_count {
+            ppu.tick();
+            dots += 1;
+        }
+        return dots;
+    };
+
+    let mut ppu = PPU::new(test_cartridge());
+    assert_eq!(262 * 341, frame_length(&mut ppu));
+    assert_eq!(262 * 341, frame_length(&mut ppu));
+
+    ppu.write_mask(0b0000_1000);
+    assert_eq!(262 * 341, frame_length(&mut ppu));
+    assert_eq!(262 * 341 - 1, frame_length(&mut ppu));
+    assert_eq!(262 * 341, frame_length(&mut ppu));
+}
@@ -0,0 +1,234 @@
+use std::f64::consts::PI;
+
+#[rustfmt::skip]
+pub static SYSTEM_PALETTE: [(u8,u8,u8); 64] = [
+   (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96),
+   (0xA1, 0x00, 0x5E), (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00),
+   (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00), (0x05, 0x4A, 0x00), (0x00, 0x47, 0x2E),
+   (0x00, 0x41, 0x66), (0x00, 0x00, 0x00), (0x05, 0x05, 0x05), (0x05, 0x05, 0x05),
+   (0xC7, 0xC7, 0xC7), (0x00, 0x77, 0xFF), (0x21, 0x55, 0xFF), (0x82, 0x37, 0xFA),
+   (0xEB, 0x2F, 0xB5), (0xFF, 0x29, 0x50), (0xFF, 0x22, 0x00), (0xD6, 0x32, 0x00),
+   (0xC4, 0x62, 0x00), (0x35, 0x80, 0x00), (0x05, 0x8F, 0x00), (0x00, 0x8A, 0x55),
+   (0x00, 0x99, 0xCC), (0x21, 0x21, 0x21), (0x09, 0x09, 0x09), (0x09, 0x09, 0x09),
+   (0xFF, 0xFF, 0xFF), (0x0F, 0xD7, 0xFF), (0x69, 0xA2, 0xFF), (0xD4, 0x80, 0xFF),
+   (0xFF, 0x45, 0xF3), (0xFF, 0x61, 0x8B), (0xFF, 0x88, 0x33), (0xFF, 0x9C, 0x12),
+   (0xFA, 0xBC, 0x20), (0x9F, 0xE3, 0x0E), (0x2B, 0xF0, 0x35), (0x0C, 0xF0, 0xA4),
+   (0x05, 0xFB, 0xFF), (0x5E, 0x5E, 0x5E), (0x0D, 0x0D, 0x0D), (0x0D, 0x0D, 0x0D),
+   (0xFF, 0xFF, 0xFF), (0xA6, 0xFC, 0xFF), (0xB3, 0xEC, 0xFF), (0xDA, 0xAB, 0xEB),
+   (0xFF, 0xA8, 0xF9), (0xFF, 0xAB, 0xB3), (0xFF, 0xD2, 0xB0), (0xFF, 0xEF, 0xA6),
+   (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
+   (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11)
+];
+
+// per mille of a color component that is left when another one is emphasized
+pub const EMPHASIS_ATTENUATION: u32 = 816;
+
+const COLORS: usize = 64;
+const EMPHASIS_VARIANTS: usize = 8;
+
+// https://www.nesdev.org/wiki/.pal
+// Either the 64 base colors, or 8 sets of them, one for every combination of the emphasis bits.
+#[derive(Clone)]
+pub struct Palette {
+    colors: Vec<(u8, u8, u8)>,
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Palette {
+            colors: SYSTEM_PALETTE.to_vec(),
+        }
+    }
+
+    // Emphasis is the red, green and blue bits of PPUMASK, shifted down to bits 0-2
+    pub fn color(&self, index: u8, emphasis: u8) -> (u8, u8, u8) {
+        let index = (index & 0x3F) as usize;
+        let emphasis = (emphasis & 0b111) as usize;
+        if self.colors.len() == COLORS * EMPHASIS_VARIANTS {
+            return self.colors[emphasis * COLORS + index];
+        }
+
+        // Emphasizing a color darkens the other two
+        let (red, green, blue) = self.colors[index];
+        let attenuate = |value: u8, is_attenuated: bool| match is_attenuated {
+            true => (value as u32 * EMPHASIS_ATTENUATION / 1000) as u8,
+            false => value,
+        };
+        let (emphasize_red, emphasize_green, emphasize_blue) =
+            (emphasis & 1 != 0, emphasis & 2 != 0, emphasis & 4 != 0);
+        return (
+            attenuate(red, emphasize_green || emphasize_blue),
+            attenuate(green, emphasize_red || emphasize_blue),
+            attenuate(blue, emphasize_red || emphasize_green),
+        );
+    }
+
+    // Color of a frame pixel, which holds the emphasis bits above the palette index
+    pub fn pixel_color(&self, pixel: u16) -> (u8, u8, u8) {
+        return self.color((pixel & 0x3F) as u8, (pixel >> 6) as u8);
+    }
+
+    // https://www.nesdev.org/wiki/NTSC_video
+    // Builds all 512 colors by decoding the composite signal the PPU would output for them.
+    // hue is in degrees, the neutral settings are (0.0, 1.0, 1.0, 0.0).
+    pub fn generate(hue: f64, saturation: f64, contrast: f64, brightness: f64) -> Self {
+        let mut colors = Vec::with_capacity(COLORS * EMPHASIS_VARIANTS);
+        for emphasis in 0..EMPHASIS_VARIANTS as u8 {
+            for index in 0..COLORS as u8 {
+                let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+                // the color carrier takes 12 samples of the signal for one period
+                for phase in 0..12 {
+                    let level = signal_level(index, emphasis, phase);
+                    let angle = carrier_angle(phase) + hue.to_radians();
+                    y += level;
+                    i += level * angle.cos();
+                    q += level * angle.sin();
+                }
+                y = y / 12.0 * contrast + brightness;
+                i = i / 12.0 * saturation;
+                q = q / 12.0 * saturation;
+                colors.push(yiq_to_rgb(y, i, q));
+            }
+        }
+        return Palette { colors: colors };
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        return Palette::new();
+    }
+}
+
+// phase of the color burst, so that the decoded hues line up with a real TV
+const NTSC_HUE_OFFSET: f64 = 4.0;
+
+// Angle of the color carrier at one of its 12 phases
+pub(crate) fn carrier_angle(phase: u8) -> f64 {
+    return PI * (phase as f64 + NTSC_HUE_OFFSET) / 6.0;
+}
+
+// FCC YIQ to RGB
+pub(crate) fn yiq_to_rgb(y: f64, i: f64, q: f64) -> (u8, u8, u8) {
+    let to_u8 = |value: f64| (value * 255.0).round().clamp(0.0, 255.0) as u8;
+    return (
+        to_u8(y + 0.946882 * i + 0.623557 * q),
+        to_u8(y - 0.274788 * i - 0.635691 * q),
+        to_u8(y - 1.108545 * i + 1.709007 * q),
+    );
+}
+
+// signal voltages for the 4 luma levels, relative to sync
+const SIGNAL_LOW: [f64; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f64; 4] = [1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f64 = 0.518;
+const SIGNAL_WHITE: f64 = 1.962;
+const SIGNAL_ATTENUATION: f64 = 0.746;
+
+// Normalized level of the square wave of a color at one of the 12 phases of the carrier
+pub(crate) fn signal_level(index: u8, emphasis: u8, phase: u8) -> f64 {
+    let hue = index & 0x0F;
+    // colors $xE and $xF are black
+    let luma = match hue {
+        0x0E | 0x0F => 1,
+        _ => (index >> 4) as usize,
+    };
+    let is_in_color_phase = |hue: u8| (hue + phase) % 12 < 6;
+
+    let mut level = match hue {
+        // gray colors don't have a carrier, $x0 stays high and $xD low
+        0x00 => SIGNAL_HIGH[luma],
+        0x0D..=0x0F => SIGNAL_LOW[luma],
+        _ if is_in_color_phase(hue) => SIGNAL_HIGH[luma],
+        _ => SIGNAL_LOW[luma],
+    };
+
+    // each emphasis bit attenuates the signal for the part of the wave of its color
+    let is_attenuated = (emphasis & 1 != 0 && is_in_color_phase(0))
+        || (emphasis & 2 != 0 && is_in_color_phase(4))
+        || (emphasis & 4 != 0 && is_in_color_phase(8));
+    if is_attenuated {
+        level = level * SIGNAL_ATTENUATION;
+    }
+    return (level - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK);
+}
+
+// 192 byte files hold the 64 base colors, 1536 byte files add the emphasis variants
+impl TryFrom<&Vec<u8>> for Palette {
+    type Error = String;
+
+    fn try_from(raw: &Vec<u8>) -> Result<Self, Self::Error> {
+        if raw.len() != COLORS * 3 && raw.len() != COLORS * EMPHASIS_VARIANTS * 3 {
+            return Err(format!(
+                "Palette files are 192 or 1536 bytes long, got {}",
+                raw.len()
+            ));
+        }
+
+        let colors = raw
+            .chunks_exact(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect();
+        return Ok(Palette { colors: colors });
+    }
+}
+
+#[cfg(test)]
+mod test_palette {
+    use super::*;
+
+    #[test]
+    fn test_try_from_192_byte_file() {
+        let raw = (0..192).map(|byte| byte as u8).collect();
+        let palette = Palette::try_from(&raw).unwrap();
+        assert_eq!((3, 4, 5), palette.color(0x01, 0));
+        // emphasis is approximated by darkening the other components
+        assert_eq!((189, 155, 155), palette.color(0x3F, 0b001));
+    }
+
+    #[test]
+    fn test_try_from_1536_byte_file_has_emphasis_variants() {
+        let mut raw = vec![0; 1536];
+        raw[5 * 192 + 3..5 * 192 + 6].copy_from_slice(&[1, 2, 3]);
+        let palette = Palette::try_from(&raw).unwrap();
+        assert_eq!((1, 2, 3), palette.color(0x01, 0b101));
+        assert_eq!((0, 0, 0), palette.color(0x01, 0));
+    }
+
+    #[test]
+    fn test_try_from_rejects_other_sizes() {
+        assert!(Palette::try_from(&vec![0; 191]).is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        let palette = Palette::generate(0.0, 1.0, 1.0, 0.0);
+        for gray in [0x00, 0x10, 0x20, 0x30, 0x0D] {
+            let (red, green, blue) = palette.color(gray, 0);
+            assert!(red == green && green == blue);
+        }
+        assert_eq!((0, 0, 0), palette.color(0x0F, 0));
+        assert_eq!((255, 255, 255), palette.color(0x20, 0));
+
+        let is_dominant = |(main, other1, other2): (u8, u8, u8)| main > other1 && main > other2;
+        let (red, green, blue) = palette.color(0x16, 0);
+        assert!(is_dominant((red, green, blue)));
+        let (red, green, blue) = palette.color(0x1A, 0);
+        assert!(is_dominant((green, red, blue)));
+        let (red, green, blue) = palette.color(0x12, 0);
+        assert!(is_dominant((blue, red, green)));
+
+        let (red, green, blue) = palette.color(0x00, 0b001);
+        assert!(red > green && red > blue);
+    }
+
+    #[test]
+    fn test_generate_applies_settings() {
+        let neutral = Palette::generate(0.0, 1.0, 1.0, 0.0);
+        let brighter = Palette::generate(0.0, 1.0, 1.0, 0.1);
+        assert!(brighter.color(0x00, 0).0 > neutral.color(0x00, 0).0);
+        let grayscale = Palette::generate(0.0, 0.0, 1.0, 0.0);
+        let (red, green, blue) = grayscale.color(0x16, 0);
+        assert!(red == green && green == blue);
+    }
+}
@@ -1,13 +1,84 @@
 use crate::ppu::registers::Register16;
 
+// Used for both the current (v) and the temporary (t) VRAM address.
+// While rendering, the bits are laid out as
+// 0yyy NNYY YYYX XXXX
+// with fine y (y), nametable (N), coarse y (Y) and coarse x (X)
+// https://www.nesdev.org/wiki/PPU_scrolling
 pub struct Address {
     pub value: u16,
 }
 
+const COARSE_X: u16 = 0b0000_0000_0001_1111;
+const COARSE_Y: u16 = 0b0000_0011_1110_0000;
+const NAMETABLE_X: u16 = 0b0000_0100_0000_0000;
+const NAMETABLE_Y: u16 = 0b0000_1000_0000_0000;
+const FINE_Y: u16 = 0b0111_0000_0000_0000;
+
 impl Address {
     pub fn new(value: u16) -> Self {
         Address { value: value }
     }
+
+    pub fn coarse_x(&self) -> u16 {
+        return self.value & COARSE_X;
+    }
+
+    pub fn coarse_y(&self) -> u16 {
+        return (self.value & COARSE_Y) >> 5;
+    }
+
+    pub fn fine_y(&self) -> u16 {
+        return (self.value & FINE_Y) >> 12;
+    }
+
+    pub fn tile_address(&self) -> u16 {
+        return 0x2000 | (self.value & 0x0FFF);
+    }
+
+    pub fn attribute_address(&self) -> u16 {
+        return 0x23C0
+            | (self.value & (NAMETABLE_X | NAMETABLE_Y))
+            | ((self.coarse_y() >> 2) << 3)
+            | (self.coarse_x() >> 2);
+    }
+
+    pub fn increment_coarse_x(&mut self) {
+        if self.coarse_x() == 31 {
+            self.value = (self.value & !COARSE_X) ^ NAMETABLE_X;
+        } else {
+            self.value += 1;
+        }
+    }
+
+    pub fn increment_y(&mut self) {
+        if self.fine_y() < 7 {
+            self.value += 1 << 12;
+            return;
+        }
+
+        self.value = self.value & !FINE_Y;
+        let coarse_y = match self.coarse_y() {
+            29 => {
+                self.value = self.value ^ NAMETABLE_Y;
+                0
+            }
+            // rows 30 and 31 hold the attribute table, the nametable doesn't switch
+            31 => 0,
+            coarse_y => coarse_y + 1,
+        };
+        self.value = (self.value & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    pub fn copy_horizontal(&mut self, other: &Address) {
+        let mask = COARSE_X | NAMETABLE_X;
+        self.value = (self.value & !mask) | (other.value & mask);
+    }
+
+    pub fn copy_vertical(&mut self, other: &Address) {
+        let mask = FINE_Y | NAMETABLE_Y | COARSE_Y;
+        self.value = (self.value & !mask) | (other.value & mask);
+    }
 }
 
 impl Register16 for Address {
@@ -19,3 +90,46 @@ impl Register16 for Address {
         self.value = data;
     }
 }
+
+#[cfg(test)]
+mod test_address {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(0b0000_0000_0000_0000, 0b0000_0000_0000_0001)]
+    #[test_case(0b0000_0000_0001_1111, 0b0000_0100_0000_0000)]
+    #[test_case(0b0000_0100_0001_1111, 0b0000_0000_0000_0000)]
+    fn test_increment_coarse_x(value: u16, expected: u16) {
+        let mut address = Address::new(value);
+        address.increment_coarse_x();
+        assert_eq!(expected, address.value);
+    }
+
+    #[test_case(0b0000_0000_0000_0000, 0b0001_0000_0000_0000)]
+    #[test_case(0b0111_0000_0000_0000, 0b0000_0000_0010_0000)]
+    #[test_case(0b0111_0011_1010_0000, 0b0000_1000_0000_0000)]
+    #[test_case(0b0111_1011_1010_0000, 0b0000_0000_0000_0000)]
+    #[test_case(0b0111_0011_1110_0000, 0b0000_0000_0000_0000)]
+    fn test_increment_y(value: u16, expected: u16) {
+        let mut address = Address::new(value);
+        address.increment_y();
+        assert_eq!(expected, address.value);
+    }
+
+    #[test]
+    fn test_copy_horizontal_and_vertical() {
+        let t = Address::new(0b0101_1110_1010_1010);
+        let mut v = Address::new(0);
+        v.copy_horizontal(&t);
+        assert_eq!(0b0000_0100_0000_1010, v.value);
+        v.copy_vertical(&t);
+        assert_eq!(0b0101_1110_1010_1010, v.value);
+    }
+
+    #[test]
+    fn test_attribute_address() {
+        // nametable 3, coarse y 29, coarse x 31
+        let v = Address::new(0b0000_1111_1011_1111);
+        assert_eq!(0x2FFF, v.attribute_address());
+    }
+}
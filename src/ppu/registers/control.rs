@@ -9,16 +9,6 @@ impl Control {
         return Control { value };
     }
 
-    pub fn nametable_base(&self) -> u16 {
-        match self.value & 0b0000_0011 {
-            0 => 0x2000,
-            1 => 0x2400,
-            2 => 0x2800,
-            3 => 0x2c00,
-            _ => panic!("Not possible"),
-        }
-    }
-
     pub fn vram_increment(&self) -> u16 {
         if (self.value & 0b0000_0100) >> 2 == 0 {
             return 1;
@@ -35,9 +25,20 @@ impl Control {
         }
     }
 
-    // todo: multiply result by 1000 and remove this logic from ppu code
-    pub fn background_pattern_table_address(&self) -> u8 {
-        return self.value & 0b0001_0000 >> 4;
+    pub fn sprite_height(&self) -> u16 {
+        if (self.value & 0b0010_0000) >> 5 == 0 {
+            return 8;
+        } else {
+            return 16;
+        }
+    }
+
+    pub fn background_pattern_table_address(&self) -> u16 {
+        if (self.value & 0b0001_0000) >> 4 == 0 {
+            return 0;
+        } else {
+            return 0x1000;
+        }
     }
 
     pub fn nmi_enable(&self) -> bool {
@@ -8,6 +8,42 @@ impl Mask {
     pub fn new(value: u8) -> Self {
         return Mask { value };
     }
+
+    pub fn is_grayscale(&self) -> bool {
+        return self.value & 0b0000_0001 != 0;
+    }
+
+    pub fn show_background_left(&self) -> bool {
+        return self.value & 0b0000_0010 != 0;
+    }
+
+    pub fn show_sprites_left(&self) -> bool {
+        return self.value & 0b0000_0100 != 0;
+    }
+
+    pub fn show_background(&self) -> bool {
+        return self.value & 0b0000_1000 != 0;
+    }
+
+    pub fn show_sprites(&self) -> bool {
+        return self.value & 0b0001_0000 != 0;
+    }
+
+    pub fn is_rendering_enabled(&self) -> bool {
+        return self.show_background() || self.show_sprites();
+    }
+
+    pub fn emphasize_red(&self) -> bool {
+        return self.value & 0b0010_0000 != 0;
+    }
+
+    pub fn emphasize_green(&self) -> bool {
+        return self.value & 0b0100_0000 != 0;
+    }
+
+    pub fn emphasize_blue(&self) -> bool {
+        return self.value & 0b1000_0000 != 0;
+    }
 }
 
 impl Register8 for Mask {
@@ -8,7 +8,17 @@ impl OAMAddress {
     pub fn new(value: u8) -> Self {
         return OAMAddress { value: value };
     }
+
+    pub fn increment(&mut self) {
+        self.value = self.value.wrapping_add(1);
+    }
+
+    // OAMDATA writes during rendering only bump the sprite number, the upper 6 bits
+    pub fn glitchy_increment(&mut self) {
+        self.value = self.value.wrapping_add(4);
+    }
 }
+
 impl Register8 for OAMAddress {
     fn read_u8(&self) -> u8 {
         return self.value;
@@ -17,7 +17,20 @@ impl Status {
     }
 
     pub fn is_in_v_blank(&self) -> bool {
-        return self.value & 0b1000_0000 >> 7 == 1;
+        return self.value & 0b1000_0000 != 0;
+    }
+
+    pub fn set_sprite_zero_hit(&mut self) {
+        self.value = self.value | 0b0100_0000;
+    }
+
+    pub fn set_sprite_overflow(&mut self) {
+        self.value = self.value | 0b0010_0000;
+    }
+
+    // vblank, sprite 0 hit and sprite overflow are all cleared on the pre-render line
+    pub fn clear_flags(&mut self) {
+        self.value = self.value & 0b0001_1111;
     }
 }
 
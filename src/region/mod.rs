@@ -0,0 +1,92 @@
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+// The CPU and the PPU run off the same master clock, each with its own divider.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    // Famiclones like the Dendy pair PAL timing with a CPU clocked closer to NTSC
+    Dendy,
+}
+
+impl Region {
+    pub fn master_clock_rate(&self) -> u32 {
+        return match self {
+            Region::Ntsc => 21_477_272,
+            Region::Pal | Region::Dendy => 26_601_712,
+        };
+    }
+
+    pub fn cpu_clock_divider(&self) -> u32 {
+        return match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        };
+    }
+
+    pub fn ppu_clock_divider(&self) -> u32 {
+        return match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        };
+    }
+
+    pub fn cpu_clock_rate(&self) -> u32 {
+        return self.master_clock_rate() / self.cpu_clock_divider();
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        return match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        };
+    }
+
+    // Dendy adds its 50 extra lines before vblank, PAL makes vblank longer
+    pub fn vblank_scanline(&self) -> u16 {
+        return match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        };
+    }
+
+    pub fn pre_render_scanline(&self) -> u16 {
+        return self.scanlines_per_frame() - 1;
+    }
+
+    // only the NTSC PPU skips a dot on odd frames
+    pub fn has_odd_frame_skip(&self) -> bool {
+        return *self == Region::Ntsc;
+    }
+
+    // PAL PPUs swap the red and green emphasis bits of PPUMASK
+    pub fn swaps_red_green_emphasis(&self) -> bool {
+        return *self != Region::Ntsc;
+    }
+
+    pub fn frames_per_second(&self) -> f64 {
+        let dots_per_frame = self.scanlines_per_frame() as f64 * 341.0;
+        let ppu_clock_rate = self.master_clock_rate() as f64 / self.ppu_clock_divider() as f64;
+        return ppu_clock_rate / dots_per_frame;
+    }
+}
+
+#[cfg(test)]
+mod test_region {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(Region::Ntsc, 1_789_772)]
+    #[test_case(Region::Pal, 1_662_607)]
+    #[test_case(Region::Dendy, 1_773_447)]
+    fn test_cpu_clock_rate(region: Region, expected: u32) {
+        assert_eq!(expected, region.cpu_clock_rate());
+    }
+
+    #[test_case(Region::Ntsc, 60.10)]
+    #[test_case(Region::Pal, 50.01)]
+    #[test_case(Region::Dendy, 50.01)]
+    fn test_frames_per_second(region: Region, expected: f64) {
+        assert!((region.frames_per_second() - expected).abs() < 0.01);
+    }
+}
@@ -1,8 +1,13 @@
+use crate::cartridge::SUPPORTED_MAPPERS;
+use crate::region::Region;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
     FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -11,6 +16,8 @@ pub struct Rom {
     pub chr_rom: Vec<u8>,
     pub mapper: u8,
     pub screen_mirroring: Mirroring,
+    // taken from the header, can be overridden before the rom is loaded
+    pub region: Region,
 }
 
 impl Rom {
@@ -25,6 +32,7 @@ impl Rom {
             chr_rom: chr_rom,
             mapper: mapper,
             screen_mirroring: screen_mirroring,
+            region: Region::Ntsc,
         }
     }
 }
@@ -39,8 +47,8 @@ impl TryFrom<&Vec<u8>> for Rom {
 
         let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
 
-        if mapper != 0 {
-            panic!("Alternative mappers are not implemented!");
+        if !SUPPORTED_MAPPERS.contains(&mapper) {
+            return Err(format!("Mapper {} is not supported", mapper));
         }
         let ines_version = (raw[7] >> 2) & 0b11;
 
@@ -57,11 +65,15 @@ impl TryFrom<&Vec<u8>> for Rom {
             (false, false) => Mirroring::Horizontal,
         };
 
-        println!("Dump file size: {}", raw.len());
+        // https://www.nesdev.org/wiki/INES#Flags_9
+        // Dendy can't be told apart from PAL by the header
+        let region = match raw[9] & 1 {
+            1 => Region::Pal,
+            _ => Region::Ntsc,
+        };
+
         const PRG_ROM_PAGE_BYTES: usize = 16384;
         let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_BYTES;
-        println!("prg rom size: {}", prg_rom_size);
-        println!("Mapper: {}", mapper);
         const CHR_ROM_PAGE_BYTES: usize = 8192;
         let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_BYTES;
 
@@ -79,6 +91,48 @@ impl TryFrom<&Vec<u8>> for Rom {
             chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
             mapper: mapper,
             screen_mirroring: mirroring,
+            region: region,
         })
     }
 }
+
+#[cfg(test)]
+mod test_rom {
+    use super::*;
+
+    fn ines_file(mapper: u8) -> Vec<u8> {
+        let mut raw = vec![
+            0x4E,
+            0x45,
+            0x53,
+            0x1A,
+            1,
+            1,
+            (mapper & 0x0F) << 4,
+            mapper & 0xF0,
+        ];
+        raw.resize(16 + 0x4000 + 0x2000, 0);
+        return raw;
+    }
+
+    #[test]
+    fn test_try_from_parses_mapper() {
+        let rom = Rom::try_from(&ines_file(1)).unwrap();
+        assert_eq!(1, rom.mapper);
+        assert_eq!(0x4000, rom.prg_rom.len());
+        assert_eq!(0x2000, rom.chr_rom.len());
+    }
+
+    #[test]
+    fn test_try_from_parses_region() {
+        let mut raw = ines_file(0);
+        assert_eq!(Region::Ntsc, Rom::try_from(&raw).unwrap().region);
+        raw[9] = 1;
+        assert_eq!(Region::Pal, Rom::try_from(&raw).unwrap().region);
+    }
+
+    #[test]
+    fn test_try_from_rejects_unsupported_mapper() {
+        assert!(Rom::try_from(&ines_file(0x42)).is_err());
+    }
+}